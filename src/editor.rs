@@ -12,7 +12,8 @@ pub enum Cursor {
     Quote(Program, Box<Cursor>, Program),
     Ident(Program, usize, Vec<char>, Program),
     StrLit(Program, usize, Vec<char>, Program),
-    NumLit(Program, Option<BigInt>, Program),
+    NumLit(Program, String, Program),
+    CharLit(Program, Option<char>, Program),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -22,14 +23,16 @@ pub enum CursorShape {
     Ident(usize, usize),
     StrLit(usize, usize),
     NumLit(usize, usize),
+    CharLit(usize, usize),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Mode {
     Normal,
     Ident,
     StrLit,
     NumLit,
+    CharLit,
 }
 
 impl Default for Cursor {
@@ -52,7 +55,11 @@ impl Cursor {
     }
 
     pub fn empty_num_lit() -> Self {
-        Self::NumLit(Vec::new(), None, Vec::new())
+        Self::NumLit(Vec::new(), String::new(), Vec::new())
+    }
+
+    pub fn empty_char_lit() -> Self {
+        Self::CharLit(Vec::new(), None, Vec::new())
     }
 
     pub fn empty_quote() -> Self {
@@ -70,6 +77,7 @@ impl Cursor {
             Self::Ident(head, _, _, tail) => CursorShape::Ident(head.len(), tail.len()),
             Self::StrLit(head, _, _, tail) => CursorShape::StrLit(head.len(), tail.len()),
             Self::NumLit(head, _, tail) => CursorShape::NumLit(head.len(), tail.len()),
+            Self::CharLit(head, _, tail) => CursorShape::CharLit(head.len(), tail.len()),
         }
     }
 
@@ -80,6 +88,7 @@ impl Cursor {
             Self::Ident(_, _, _, _) => Mode::Ident,
             Self::StrLit(_, _, _, _) => Mode::StrLit,
             Self::NumLit(_, _, _) => Mode::NumLit,
+            Self::CharLit(_, _, _) => Mode::CharLit,
         }
     }
 
@@ -125,6 +134,15 @@ impl Cursor {
             Self::Ident(_, _, _, tail) => tail.get(0),
             Self::StrLit(_, _, _, tail) => tail.get(0),
             Self::NumLit(_, _, tail) => tail.get(0),
+            Self::CharLit(_, _, tail) => tail.first(),
+        }
+    }
+
+    pub fn prev_expr(&self) -> Option<&Expr> {
+        match self {
+            Self::Edge(head, _) => head.last(),
+            Self::Quote(_, cursor, _) => cursor.prev_expr(),
+            _ => None,
         }
     }
 
@@ -145,10 +163,15 @@ impl Cursor {
                 head.push(Expr::StrLit(s.into_iter().collect()));
                 Self::Edge(head, tail)
             },
-            Self::NumLit(mut head, n, tail) => {
-                match n {
-                    Some(n) => head.push(Expr::NumLit(n)),
-                    None => (),
+            Self::NumLit(mut head, s, tail) => {
+                if let Ok(n) = s.parse::<BigInt>() {
+                    head.push(Expr::NumLit(n));
+                }
+                Self::Edge(head, tail)
+            },
+            Self::CharLit(mut head, c, tail) => {
+                if let Some(c) = c {
+                    head.push(Expr::CharLit(c));
                 }
                 Self::Edge(head, tail)
             },
@@ -176,6 +199,7 @@ impl Cursor {
                 }
             },
             Self::NumLit(_, _, _) => {},
+            Self::CharLit(_, _, _) => {},
         }
     }
 
@@ -195,6 +219,59 @@ impl Cursor {
                 *n = 0;
             },
             Self::NumLit(_, _, _) => {},
+            Self::CharLit(_, _, _) => {},
+        }
+    }
+
+    pub fn move_very_right(&mut self) {
+        match self {
+            Self::Edge(head, tail) => {
+                head.append(tail);
+            },
+            Self::Quote(_, cursor, _) => {
+                cursor.move_very_right();
+            },
+            Self::Ident(_, n, s, _) => {
+                *n = s.len();
+            },
+            Self::StrLit(_, n, s, _) => {
+                *n = s.len();
+            },
+            Self::NumLit(_, _, _) => {},
+            Self::CharLit(_, _, _) => {},
+        }
+    }
+
+    fn is_next_to_quote(&self) -> bool {
+        match self {
+            Self::Edge(head, tail) => {
+                matches!(head.last(), Some(Expr::Quote(_))) || matches!(tail.first(), Some(Expr::Quote(_)))
+            },
+            _ => false,
+        }
+    }
+
+    pub fn jump_matching(&mut self) {
+        match self {
+            Self::Edge(head, tail) => {
+                if let Some(Expr::Quote(_)) = head.last() {
+                    self.move_up();
+                    self.move_very_left();
+                } else if let Some(Expr::Quote(_)) = tail.first() {
+                    self.move_right();
+                    self.move_up();
+                }
+            },
+            Self::Quote(_, cursor, _) => {
+                let next_to_quote = cursor.is_next_to_quote();
+                match cursor.as_mut() {
+                    Self::Edge(_, _) if next_to_quote => cursor.jump_matching(),
+                    Self::Edge(head, _) if head.is_empty() => cursor.move_very_right(),
+                    Self::Edge(_, _) => cursor.move_very_left(),
+                    _ => cursor.jump_matching(),
+                }
+            },
+            _ => {},
         }
     }
 
@@ -220,6 +297,80 @@ impl Cursor {
                 }
             },
             Self::NumLit(_, _, _) => {},
+            Self::CharLit(_, _, _) => {},
+        }
+    }
+
+    pub fn move_word_left(&mut self) {
+        match self {
+            Self::Edge(head, tail) => {
+                if let Some(Expr::Quote(_)) = head.last() {
+                    tail.insert(0, head.pop().unwrap());
+                    return;
+                }
+                while let Some(expr) = head.pop() {
+                    if let Expr::Quote(_) = expr {
+                        head.push(expr);
+                        break;
+                    }
+                    tail.insert(0, expr);
+                }
+            },
+            Self::Quote(_, cursor, _) => {
+                cursor.move_word_left();
+            },
+            _ => {},
+        }
+    }
+
+    pub fn move_word_right(&mut self) {
+        match self {
+            Self::Edge(head, tail) => {
+                if let Some(Expr::Quote(_)) = tail.first() {
+                    head.push(tail.remove(0));
+                    return;
+                }
+                while !tail.is_empty() {
+                    if let Expr::Quote(_) = tail[0] {
+                        break;
+                    }
+                    head.push(tail.remove(0));
+                }
+            },
+            Self::Quote(_, cursor, _) => {
+                cursor.move_word_right();
+            },
+            _ => {},
+        }
+    }
+
+    pub fn swap_left(&mut self) {
+        match self {
+            Self::Edge(head, tail) => {
+                if head.len() >= 2 {
+                    let expr = head.remove(head.len() - 2);
+                    tail.insert(0, expr);
+                }
+            },
+            Self::Quote(_, cursor, _) => {
+                cursor.swap_left();
+            },
+            _ => {},
+        }
+    }
+
+    pub fn swap_right(&mut self) {
+        match self {
+            Self::Edge(head, tail) => {
+                if !head.is_empty() && !tail.is_empty() {
+                    let expr = tail.remove(0);
+                    head.insert(head.len() - 1, expr);
+                }
+            },
+            Self::Quote(_, cursor, _) => {
+                cursor.swap_right();
+            },
+            _ => {},
         }
     }
 
@@ -249,6 +400,31 @@ impl Cursor {
         }
     }
 
+    pub fn move_down(&mut self) {
+        *self = match mem::take(self) {
+            Self::Edge(head, mut tail) => {
+                match tail.first() {
+                    Some(Expr::Quote(_)) => {
+                        let Expr::Quote(program) = tail.remove(0) else { unreachable!() };
+                        let mut cursor = Self::Edge(Vec::new(), program);
+                        cursor.move_very_right();
+                        Self::Quote(head, Box::new(cursor), tail)
+                    },
+                    _ => {
+                        Self::Edge(head, tail)
+                    },
+                }
+            },
+            Self::Quote(head, mut cursor, tail) => {
+                cursor.move_down();
+                Self::Quote(head, cursor, tail)
+            },
+            cursor => {
+                cursor
+            },
+        }
+    }
+
     pub fn move_out(&mut self) {
         *self = match mem::take(self) {
             Self::Quote(mut head, mut cursor, tail) => {
@@ -293,6 +469,11 @@ impl Cursor {
                 stail.append(&mut tail);
                 Self::NumLit(head, n, stail)
             },
+            (Self::Edge(mut head, mut tail), Self::CharLit(mut shead, c, mut stail)) => {
+                head.append(&mut shead);
+                stail.append(&mut tail);
+                Self::CharLit(head, c, stail)
+            },
             (Self::Quote(head, mut cursor, tail), subst) => {
                 cursor.insert(subst);
                 Self::Quote(head, cursor, tail)
@@ -317,6 +498,94 @@ impl Cursor {
         }
     }
 
+    pub fn delete_after(&mut self) {
+        match self {
+            Self::Edge(_, tail) => {
+                if !tail.is_empty() {
+                    tail.remove(0);
+                }
+            },
+            Self::Quote(_, cursor, _) => {
+                cursor.delete_after();
+            },
+            _ => {
+                panic!();
+            },
+        }
+    }
+
+    pub fn wrap_in_quote(&mut self) {
+        match self {
+            Self::Edge(head, _) => {
+                if let Some(expr) = head.pop() {
+                    head.push(Expr::Quote(vec![expr]));
+                }
+            },
+            Self::Quote(_, cursor, _) => {
+                cursor.wrap_in_quote();
+            },
+            _ => {
+                panic!();
+            },
+        }
+    }
+
+    pub fn wrap_all_in_quote(&mut self) {
+        *self = Self::Edge(vec![Expr::Quote(self.program())], Vec::new());
+    }
+
+    pub fn unwrap_quote(&mut self) {
+        *self = match mem::take(self) {
+            Self::Edge(mut head, mut tail) => {
+                if let Some(Expr::Quote(_)) = head.last() {
+                    let Some(Expr::Quote(mut program)) = head.pop() else { unreachable!() };
+                    head.append(&mut program);
+                } else if let Some(Expr::Quote(_)) = tail.first() {
+                    let Expr::Quote(mut program) = tail.remove(0) else { unreachable!() };
+                    program.append(&mut tail);
+                    tail = program;
+                }
+                Self::Edge(head, tail)
+            },
+            Self::Quote(mut head, mut cursor, mut tail) => {
+                let next_to_quote = cursor.is_next_to_quote();
+                match *cursor {
+                    Self::Edge(mut shead, mut stail) if !next_to_quote => {
+                        head.append(&mut shead);
+                        stail.append(&mut tail);
+                        Self::Edge(head, stail)
+                    },
+                    _ => {
+                        cursor.unwrap_quote();
+                        Self::Quote(head, cursor, tail)
+                    },
+                }
+            },
+            cursor => {
+                cursor
+            },
+        }
+    }
+
+    pub fn delete_char(&mut self) {
+        match self {
+            Self::Edge(_, _) => {},
+            Self::Quote(_, cursor, _) => {
+                cursor.delete_char();
+            },
+            Self::Ident(_, n, s, _) | Self::StrLit(_, n, s, _) => {
+                if *n > 0 {
+                    *n -= 1;
+                    s.remove(*n);
+                }
+            },
+            Self::NumLit(_, s, _) => {
+                s.pop();
+            },
+            Self::CharLit(_, _, _) => {},
+        }
+    }
+
     pub fn input(&mut self, c: char) {
         match self {
             Self::Edge(_, _) =>
@@ -331,11 +600,15 @@ impl Cursor {
                 s.insert(*n, c);
                 *n += 1;
             },
-            Self::NumLit(_, n, _) => {
-                if let Some(digit) = c.to_digit(10) {
-                    *n = Some(n.take().unwrap_or_default() * 10 + digit);
+            Self::NumLit(_, s, _) => {
+                if c.is_ascii_digit() || (c == '-' && s.is_empty()) {
+                    s.push(c);
                 }
             },
+            Self::CharLit(_, slot, _) => {
+                *slot = Some(c);
+                self.escape_to_normal();
+            },
         }
     }
 }
@@ -381,18 +654,15 @@ impl PrettyText for Cursor {
                 }
                 tail.get_text(text);
             },
-            Self::NumLit(head, n, tail) => {
+            Self::NumLit(head, s, tail) => {
                 head.get_text(text);
                 if !head.is_empty() {
                     text.write_str_default(" ");
                 }
-                match n {
-                    Some(n) => {
-                        text.write_str(Color::Green, Color::Black, &format!("{n}"));
-                    },
-                    None => {
-                        text.write_str(Color::Green, Color::Black, "0");
-                    },
+                if s.is_empty() {
+                    text.write_str(Color::Green, Color::Black, "0");
+                } else {
+                    text.write_str(Color::Green, Color::Black, s);
                 }
                 text.write_str(Color::Magenta, Color::Magenta, " ");
                 if !tail.is_empty() {
@@ -400,6 +670,78 @@ impl PrettyText for Cursor {
                 }
                 tail.get_text(text);
             },
+            Self::CharLit(head, _, tail) => {
+                head.get_text(text);
+                if !head.is_empty() {
+                    text.write_str_default(" ");
+                }
+                text.write_str(Color::Green, Color::Black, "'");
+                text.write_str(Color::Magenta, Color::Magenta, " ");
+                if !tail.is_empty() {
+                    text.write_str_default(" ");
+                }
+                tail.get_text(text);
+            },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::{parse, to_source};
+
+    fn edge(head: &str, tail: &str) -> Cursor {
+        Cursor::Edge(parse(head).unwrap(), parse(tail).unwrap())
+    }
+
+    #[test]
+    fn unwrap_quote_before_cursor() {
+        let mut cursor = edge("0 {1 2}", "3");
+        cursor.unwrap_quote();
+        assert_eq!(cursor, edge("0 1 2", "3"));
+    }
+
+    #[test]
+    fn unwrap_quote_after_cursor() {
+        let mut cursor = edge("0", "{1 2} 3");
+        cursor.unwrap_quote();
+        assert_eq!(cursor, edge("0", "1 2 3"));
+    }
+
+    #[test]
+    fn unwrap_quote_around_cursor() {
+        let mut cursor = Cursor::Quote(parse("0").unwrap(), Box::new(edge("1", "{2}")), parse("3").unwrap());
+        cursor.unwrap_quote();
+        assert_eq!(to_source(&cursor.program()), "0 {1 2} 3");
+        cursor.unwrap_quote();
+        assert_eq!(cursor, edge("0 1", "2 3"));
+    }
+
+    #[test]
+    fn wrap_in_quote_wraps_previous_expr() {
+        let mut cursor = edge("1 dup", "2");
+        cursor.wrap_in_quote();
+        assert_eq!(cursor, edge("1 {dup}", "2"));
+    }
+
+    #[test]
+    fn word_motion_stops_at_quotes() {
+        let mut cursor = edge("", "1 dup + {2} 3");
+        cursor.move_word_right();
+        assert_eq!(cursor, edge("1 dup +", "{2} 3"));
+        cursor.move_word_right();
+        assert_eq!(cursor, edge("1 dup + {2}", "3"));
+        cursor.move_word_left();
+        assert_eq!(cursor, edge("1 dup +", "{2} 3"));
+        cursor.move_word_left();
+        assert_eq!(cursor, edge("", "1 dup + {2} 3"));
+    }
+
+    #[test]
+    fn wrap_all_in_quote_wraps_program() {
+        let mut cursor = edge("1 2", "+");
+        cursor.wrap_all_in_quote();
+        assert_eq!(cursor.program(), parse("{1 2 +}").unwrap());
+    }
+}
@@ -1,38 +1,165 @@
+use std::cell::RefCell;
 use std::cmp::max;
-use std::collections::HashMap;
-use std::iter::repeat;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::iter::{repeat, repeat_n};
+use std::mem;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use num_bigint::{BigInt, Sign};
 use terminal::Color;
 use crate::{
     polyset::Polyset,
     syntax::{Expr},
     editor::{Cursor, CursorShape},
-    value::Value,
-    pretty::{Pretty, Layout},
+    value::{Value, Float},
+    pretty::{Pretty, PrettyText, Layout, TextOptions},
 };
 
 #[derive(Debug, Clone)]
 pub struct VM {
     parent: Option<Box<VM>>,
     stack: Vec<Value>,
+    input: Rc<RefCell<Input>>,
+    clock: Clock,
+    max_iterations: usize,
+    step: Option<CursorShape>,
+}
+
+#[derive(Debug)]
+pub struct Input {
+    lines: VecDeque<String>,
+    pending: Option<String>,
 }
 
 pub type Trace = HashMap<CursorShape, Vec<VM>>;
 
+pub type Clock = fn() -> Option<i64>;
+
+pub const ITERATION_LIMIT: usize = 10000;
+
+pub const PRIMITIVES: &[&str] = &[
+    "del", "clear", "dup", "rep", "flip", "pick", "copy", "move", "sb", "s",
+    "inc", "+", "*", "/", "==", "eqnum", "deepeq", "=<", ">=", "between", "cmp", "and", "or",
+    "read", "lines", "words", "wordstats", "wordstatsi", "ssplit", "startswith", "endswith", "contains", "trim", "triml", "trimr", "upper", "lower", "swapcase", "title", "indexof", "strrepeat", "strjoin", "split", "splitat", "take", "irange", "crange", "indexed", "invert", "num", "c2n", "n2c", "ord", "chr", "bits",
+    "collect", "each", "reach", "set", "nub", "sortuniq", "uniq", "distinct", "card", "mult", "mostcommon", "ndistinct", "iota", "at", "chunks", "chunksizes", "frames", "pairs", "inits", "tails", "deal", "interleave", "intersperse", "rle", "unrle",
+    "len", "sum", "cumsum", "diff", "product", "max", "maxwindow", "longest", "sort", "rsort", "append", "merge", "find", "rfind", "union", "join", "intersect", "setdiff", "overlap",
+    "map", "foldr", "windowmap", "pairwise", "groupbykey", "uniqueby", "chunkwhile", "countif", "withprev", "span", "power", "fixpoint", "unfold", "under", "shape", "sameshape", "isrect", "coords", "prompt", "now",
+];
+
+impl Input {
+    fn read_line(&mut self, prompt: String) -> Option<String> {
+        let line = self.lines.pop_front();
+        if line.is_none() && self.pending.is_none() {
+            self.pending = Some(prompt);
+        }
+        line
+    }
+}
+
+fn parse_num(s: &str) -> Option<BigInt> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (radix, digits) = if let Some(digits) = digits.strip_prefix("0x") {
+        (16, digits)
+    } else if let Some(digits) = digits.strip_prefix("0b") {
+        (2, digits)
+    } else {
+        (10, digits)
+    };
+    if digits.starts_with(['+', '-']) || digits.contains('_') {
+        return None;
+    }
+    let n = BigInt::parse_bytes(digits.as_bytes(), radix)?;
+    Some(if negative { -n } else { n })
+}
+
+fn to_number(value: &Value) -> Option<Value> {
+    if value.is_float() {
+        Some(value.clone())
+    } else {
+        Some(Value::new_num(value.as_num()?))
+    }
+}
+
+fn add(a: &Value, b: &Value) -> Option<Value> {
+    if a.is_float() || b.is_float() {
+        Some(Value::new_float(a.as_float()? + b.as_float()?))
+    } else {
+        Some(Value::new_num(a.as_num()? + b.as_num()?))
+    }
+}
+
+fn sub(a: &Value, b: &Value) -> Option<Value> {
+    if a.is_float() || b.is_float() {
+        Some(Value::new_float(a.as_float()? - b.as_float()?))
+    } else {
+        Some(Value::new_num(a.as_num()? - b.as_num()?))
+    }
+}
+
+fn mul(a: &Value, b: &Value) -> Option<Value> {
+    if a.is_float() || b.is_float() {
+        Some(Value::new_float(a.as_float()? * b.as_float()?))
+    } else {
+        Some(Value::new_num(a.as_num()? * b.as_num()?))
+    }
+}
+
+fn system_clock() -> Option<i64> {
+    SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_millis().try_into().ok()
+}
+
 impl VM {
-    pub fn new() -> Self {
+    pub fn with_input(lines: Vec<String>) -> Self {
         Self {
             parent: None,
             stack: Vec::new(),
+            input: Rc::new(RefCell::new(Input {
+                lines: lines.into(),
+                pending: None,
+            })),
+            clock: system_clock,
+            max_iterations: ITERATION_LIMIT,
+            step: None,
         }
     }
 
+    #[cfg(test)]
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
     pub fn new_child(&self) -> Self {
         Self {
             parent: Some(Box::new(self.clone())),
             stack: Vec::new(),
+            input: self.input.clone(),
+            clock: self.clock,
+            max_iterations: self.max_iterations,
+            step: None,
         }
     }
 
+    pub fn stack(&self) -> &[Value] {
+        &self.stack
+    }
+
+    pub fn origin(&self) -> Option<&CursorShape> {
+        self.parent.as_ref()?.step.as_ref()
+    }
+
+    pub fn pending_prompt(&self) -> Option<String> {
+        self.input.borrow().pending.clone()
+    }
+
     fn add_snapshot(&mut self, trace: &mut Trace, key: CursorShape) {
         trace.entry(key).or_insert(Vec::new()).push(self.clone());
     }
@@ -41,6 +168,46 @@ impl VM {
         self.stack.pop()
     }
 
+    fn pop_any(&mut self) -> Result<Value, &'static str> {
+        self.pop().ok_or("empty stack")
+    }
+
+    fn pop_as<T>(&mut self, reason: &'static str, convert: impl FnOnce(&Value) -> Option<T>) -> Result<T, &'static str> {
+        convert(&self.pop_any()?).ok_or(reason)
+    }
+
+    fn pop_bool(&mut self) -> Result<bool, &'static str> {
+        self.pop_as("expected boolean", Value::as_bool)
+    }
+
+    fn pop_char(&mut self) -> Result<char, &'static str> {
+        self.pop_as("expected char", Value::as_char)
+    }
+
+    fn pop_list(&mut self) -> Result<Vec<Value>, &'static str> {
+        self.pop_as("expected list", Value::as_list)
+    }
+
+    fn pop_num(&mut self) -> Result<Value, &'static str> {
+        self.pop_as("expected number", to_number)
+    }
+
+    fn pop_set(&mut self) -> Result<Polyset<Value>, &'static str> {
+        self.pop_as("expected set", Value::as_set)
+    }
+
+    fn pop_string(&mut self) -> Result<String, &'static str> {
+        self.pop_as("expected string", Value::as_string)
+    }
+
+    fn pop_usize(&mut self) -> Result<usize, &'static str> {
+        self.pop_as("expected non-negative integer", Value::as_usize)
+    }
+
+    fn pop_i64(&mut self) -> Result<i64, &'static str> {
+        self.pop_as("expected integer", Value::as_i64)
+    }
+
     fn push(&mut self, value: Value) {
         self.stack.push(value);
     }
@@ -49,47 +216,57 @@ impl VM {
         self.stack.extend(values);
     }
 
+    fn apply(&self, trace: &mut Trace, cursor: &Cursor, args: impl IntoIterator<Item = Value>) -> Result<Value, &'static str> {
+        let mut vm = self.new_child();
+        vm.push_all(args);
+        vm.eval_cursor(trace, cursor.clone());
+        vm.pop().ok_or("quote left an empty stack")
+    }
+
     fn eval_prim(&mut self, trace: &mut Trace, prim: &str) {
-        let result = try {
+        let result: Result<(), &str> = try {
             match prim {
                 "del" => {
-                    self.pop()?;
+                    self.pop_any()?;
+                },
+                "clear" => {
+                    self.stack.clear();
                 },
                 "dup" => {
-                    let value = self.pop()?;
+                    let value = self.pop_any()?;
                     self.push(value.clone());
                     self.push(value);
                 },
                 "rep" => {
-                    let count = self.pop()?.as_usize()?;
-                    let value = self.pop()?;
+                    let count = self.pop_usize()?;
+                    let value = self.pop_any()?;
                     self.push_all(repeat(value).take(count));
                 },
                 "flip" => {
-                    let fst = self.pop()?;
-                    let snd = self.pop()?;
+                    let fst = self.pop_any()?;
+                    let snd = self.pop_any()?;
                     self.push(fst);
                     self.push(snd);
                 },
-                "copy" => {
-                    let index = self.pop()?.as_usize()?;
-                    let value = self.stack.get(self.stack.len() - 1 - index)?.clone();
+                "pick" | "copy" => {
+                    let index = self.pop_usize()?;
+                    let value = index.checked_add(1).and_then(|n| self.stack.len().checked_sub(n)).and_then(|i| self.stack.get(i)).ok_or("index out of range")?.clone();
                     self.push(value);
                 },
                 "move" => {
-                    let offset = self.pop()?.as_usize()?;
+                    let offset = self.pop_usize()?;
                     let index = self.stack.len() - 1 - offset;
                     if index < self.stack.len() {
                         let value = self.stack.remove(index);
                         self.push(value);
                     } else {
-                        None?
+                        Err("index out of range")?
                     }
                 },
                 "sb" => {
-                    let new = self.pop()?;
-                    let test = self.pop()?;
-                    let value = self.pop()?;
+                    let new = self.pop_any()?;
+                    let test = self.pop_any()?;
+                    let value = self.pop_any()?;
                     if value == test {
                         self.push(new);
                     } else {
@@ -97,61 +274,113 @@ impl VM {
                     }
                 },
                 "s" => {
-                    let arg3 = self.pop()?;
-                    let arg2 = self.pop()?;
-                    let haystack = self.pop()?.as_string()?;
-                    self.push(Value::new_str(&haystack.replace(&arg2.as_string()?, &arg3.as_string()?)));
+                    let arg3 = self.pop_any()?;
+                    let arg2 = self.pop_any()?;
+                    let haystack = self.pop_string()?;
+                    self.push(Value::new_str(&haystack.replace(&arg2.as_string().ok_or("expected string")?, &arg3.as_string().ok_or("expected string")?)));
                 },
                 "inc" => {
-                    let a = self.pop()?.as_num()?;
-                    self.push(Value::new_num(a + 1));
+                    let a = self.pop_any()?;
+                    if a.is_float() {
+                        self.push(Value::new_float(a.as_float().ok_or("expected number")? + 1.0));
+                    } else {
+                        self.push(Value::new_num(a.as_num().ok_or("expected number")? + 1));
+                    }
                 },
                 "+" => {
-                    let b = self.pop()?.as_num()?;
-                    let a = self.pop()?.as_num()?;
-                    self.push(Value::new_num(a + b));
+                    let b = self.pop_any()?;
+                    let a = self.pop_any()?;
+                    self.push(add(&a, &b).ok_or("expected number")?);
                 },
                 "*" => {
-                    let b = self.pop()?.as_num()?;
-                    let a = self.pop()?.as_num()?;
-                    self.push(Value::new_num(a * b));
+                    let b = self.pop_any()?;
+                    let a = self.pop_any()?;
+                    self.push(mul(&a, &b).ok_or("expected number")?);
                 },
                 "/" => {
-                    let b = self.pop()?.as_num()?;
-                    let a = self.pop()?.as_num()?;
-                    self.push(Value::new_num(a / b));
+                    let b = self.pop_any()?;
+                    let a = self.pop_any()?;
+                    if b.as_num() == Some(BigInt::from(0)) {
+                        Err("division by zero")?
+                    }
+                    if a.is_float() || b.is_float() {
+                        self.push(Value::new_float(a.as_float().ok_or("expected number")? / b.as_float().ok_or("expected number")?));
+                    } else {
+                        self.push(Value::new_num(a.as_num().ok_or("expected number")? / b.as_num().ok_or("expected number")?));
+                    }
                 },
                 "==" => {
-                    let b = self.pop()?;
-                    let a = self.pop()?;
+                    let b = self.pop_any()?;
+                    let a = self.pop_any()?;
+                    self.push(Value::new_bool(a == b));
+                },
+                "eqnum" => {
+                    let coerce = |value: Value| to_number(&value).or_else(|| {
+                        let s = value.as_string()?;
+                        match (parse_num(&s), s.parse()) {
+                            (Some(n), _) => Some(Value::new_num(n)),
+                            (None, Ok(f)) if s.contains('.') => Some(Value::new_float(f)),
+                            _ => None,
+                        }
+                    });
+                    let b = coerce(self.pop_any()?).ok_or("expected number")?;
+                    let a = coerce(self.pop_any()?).ok_or("expected number")?;
                     self.push(Value::new_bool(a == b));
                 },
+                "deepeq" => {
+                    let b = self.pop_any()?;
+                    let a = self.pop_any()?;
+                    self.push(Value::new_bool(a.deep_eq(&b)));
+                },
                 "=<" => {
-                    let b = self.pop()?.as_num()?;
-                    let a = self.pop()?.as_num()?;
-                    self.push(Value::new_bool(a <= b));
+                    let b = self.pop_any()?;
+                    let a = self.pop_any()?;
+                    if a.is_float() || b.is_float() {
+                        self.push(Value::new_bool(a.as_float().ok_or("expected number")? <= b.as_float().ok_or("expected number")?));
+                    } else {
+                        self.push(Value::new_bool(a.as_num().ok_or("expected number")? <= b.as_num().ok_or("expected number")?));
+                    }
                 },
                 ">=" => {
-                    let b = self.pop()?.as_num()?;
-                    let a = self.pop()?.as_num()?;
-                    self.push(Value::new_bool(a >= b));
+                    let b = self.pop_any()?;
+                    let a = self.pop_any()?;
+                    if a.is_float() || b.is_float() {
+                        self.push(Value::new_bool(a.as_float().ok_or("expected number")? >= b.as_float().ok_or("expected number")?));
+                    } else {
+                        self.push(Value::new_bool(a.as_num().ok_or("expected number")? >= b.as_num().ok_or("expected number")?));
+                    }
+                },
+                "between" => {
+                    let upper = self.pop_num()?;
+                    let lower = self.pop_num()?;
+                    let value = self.pop_num()?;
+                    self.push(Value::new_bool(lower <= value && value <= upper));
+                },
+                "cmp" => {
+                    let b = self.pop_list()?;
+                    let a = self.pop_list()?;
+                    if a.len() != b.len() {
+                        Err("length mismatch")?
+                    }
+                    let result = a.iter().zip(b.iter()).map(|(x, y)| Value::new_i64(x.cmp(y) as i64));
+                    self.push(Value::new_list(result.collect()));
                 },
                 "and" => {
-                    let b = self.pop()?.as_bool()?;
-                    let a = self.pop()?.as_bool()?;
+                    let b = self.pop_bool()?;
+                    let a = self.pop_bool()?;
                     self.push(Value::new_bool(a && b));
                 },
                 "or" => {
-                    let b = self.pop()?.as_bool()?;
-                    let a = self.pop()?.as_bool()?;
+                    let b = self.pop_bool()?;
+                    let a = self.pop_bool()?;
                     self.push(Value::new_bool(a || b));
                 },
                 "read" => {
-                    let contents = std::fs::read_to_string(self.pop()?.as_string()?).ok()?;
+                    let contents = std::fs::read_to_string(self.pop_string()?).map_err(|_| "file not found")?;
                     self.push(Value::new_str(&contents));
                 },
                 "lines" => {
-                    let arg = self.pop()?.as_string()?;
+                    let arg = self.pop_string()?;
                     let lines = arg.split('\n');
                     let mut result: Vec<_> = lines.map(Value::new_str).collect();
                     if result.last() == Some(&Value::new_str("")) {
@@ -160,20 +389,114 @@ impl VM {
                     self.push(Value::new_list(result));
                 },
                 "words" => {
-                    let arg = self.pop()?.as_string()?;
+                    let arg = self.pop_string()?;
                     let words = arg.split(|c: char| !c.is_alphanumeric());
                     self.push(Value::new_list(words.map(|word| Value::new_str(word)).collect()));
                 },
+                "wordstats" | "wordstatsi" => {
+                    let mut arg = self.pop_string()?;
+                    if prim == "wordstatsi" {
+                        arg = arg.to_lowercase();
+                    }
+                    let words = arg.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty());
+                    self.push(Value::new_set(words.map(Value::new_str).collect()));
+                },
+                "ssplit" => {
+                    let separator = self.pop_string()?;
+                    let haystack = self.pop_string()?;
+                    if separator.is_empty() {
+                        Err("empty separator")?
+                    }
+                    self.push(Value::new_list(haystack.split(&separator).map(Value::new_str).collect()));
+                },
+                "startswith" => {
+                    let needle = self.pop_string()?;
+                    let haystack = self.pop_string()?;
+                    self.push(Value::new_bool(haystack.starts_with(&needle)));
+                },
+                "endswith" => {
+                    let needle = self.pop_string()?;
+                    let haystack = self.pop_string()?;
+                    self.push(Value::new_bool(haystack.ends_with(&needle)));
+                },
+                "contains" => {
+                    let needle = self.pop_string()?;
+                    let haystack = self.pop_string()?;
+                    self.push(Value::new_bool(haystack.contains(&needle)));
+                },
+                "trim" => {
+                    let arg = self.pop_string()?;
+                    self.push(Value::new_str(arg.trim()));
+                },
+                "triml" => {
+                    let arg = self.pop_string()?;
+                    self.push(Value::new_str(arg.trim_start()));
+                },
+                "trimr" => {
+                    let arg = self.pop_string()?;
+                    self.push(Value::new_str(arg.trim_end()));
+                },
+                "upper" => {
+                    let arg = self.pop_string()?;
+                    self.push(Value::new_str(&arg.to_uppercase()));
+                },
+                "lower" => {
+                    let arg = self.pop_string()?;
+                    self.push(Value::new_str(&arg.to_lowercase()));
+                },
+                "swapcase" => {
+                    let arg = self.pop_string()?;
+                    let mut swapped = String::new();
+                    for c in arg.chars() {
+                        if c.is_uppercase() {
+                            swapped.extend(c.to_lowercase());
+                        } else {
+                            swapped.extend(c.to_uppercase());
+                        }
+                    }
+                    self.push(Value::new_str(&swapped));
+                },
+                "title" => {
+                    let arg = self.pop_string()?;
+                    let mut titled = String::new();
+                    let mut word_start = true;
+                    for c in arg.chars() {
+                        if word_start {
+                            titled.extend(c.to_uppercase());
+                        } else {
+                            titled.extend(c.to_lowercase());
+                        }
+                        word_start = c.is_whitespace();
+                    }
+                    self.push(Value::new_str(&titled));
+                },
+                "indexof" => {
+                    let needle = self.pop_string()?;
+                    let haystack = self.pop_string()?;
+                    let offset = haystack.find(&needle).ok_or("not found")?;
+                    self.push(Value::new_i64(haystack[.. offset].chars().count() as i64));
+                },
+                "strrepeat" => {
+                    let count = self.pop_usize()?;
+                    let arg = self.pop_string()?;
+                    self.push(Value::new_str(&arg.repeat(count)));
+                },
+                "strjoin" => {
+                    let sep = self.pop_string()?;
+                    let arg = self.pop_any()?;
+                    let strings = arg.as_slice().ok_or("expected list")?.iter().map(Value::as_string).collect::<Option<Vec<_>>>().ok_or("expected list of strings")?;
+                    self.push(Value::new_str(&strings.join(&sep)));
+                },
                 "split" => {
-                    let sep = self.pop()?;
-                    let list = self.pop()?.as_list()?;
+                    let sep = self.pop_any()?;
+                    let list = self.pop_list()?;
                     let pieces = list.split(|v| *v == sep);
                     self.push(Value::new_list(pieces.map(|piece| Value::new_list(piece.into_iter().cloned().collect())).collect()));
                 },
                 "splitat" => {
-                    let mut index = self.pop()?.as_i64()?;
-                    let arg = self.pop()?;
-                    let mut list: Vec<_> = arg.as_list()?;
+                    let mut index = self.pop_i64()?;
+                    let arg = self.pop_any()?;
+                    let mut list: Vec<_> = arg.as_list().ok_or("expected list")?;
                     if index < 0 {
                         index = list.len() as i64 + index;
                     }
@@ -181,8 +504,8 @@ impl VM {
                     self.push(Value::new_list(list));
                 },
                 "take" => {
-                    let mut count = self.pop()?.as_i64()?;
-                    let mut list: Vec<_> = self.pop()?.as_list()?;
+                    let mut count = self.pop_i64()?;
+                    let mut list: Vec<_> = self.pop_list()?;
                     if count < 0 {
                         count = max(0, list.len() as i64 + count);
                     }
@@ -190,146 +513,407 @@ impl VM {
                     self.push(Value::new_list(list))
                 },
                 "irange" => {
-                    let upper = self.pop()?.as_i64()?;
-                    let lower = self.pop()?.as_i64()?;
+                    let upper = self.pop_i64()?;
+                    let lower = self.pop_i64()?;
                     self.push(Value::new_list((lower ..= upper).map(|n| Value::new_i64(n)).collect()));
                 },
                 "crange" => {
-                    let upper = self.pop()?.as_char()?;
-                    let lower = self.pop()?.as_char()?;
+                    let upper = self.pop_char()?;
+                    let lower = self.pop_char()?;
                     self.push(Value::new_list((lower ..= upper).map(Value::new_char).collect()));
                 },
                 "indexed" => {
-                    let list = self.pop()?.as_list()?;
+                    let list = self.pop_list()?;
                     let indexed = list.iter().enumerate().map(|(i, v)| {
                         Value::new_list(vec![Value::new_i64(i as i64), v.clone()])
                     });
                     self.push(Value::new_list(indexed.collect()));
                 },
                 "num" => {
-                    let arg = self.pop()?.as_string()?;
-                    match arg.parse() {
-                        Ok(n) => self.push(Value::new_num(n)),
-                        Err(_) => self.push(Value::new_poison()),
+                    let arg = self.pop_string()?;
+                    match (parse_num(&arg), arg.parse()) {
+                        (Some(n), _) => self.push(Value::new_num(n)),
+                        (None, Ok(f)) if arg.contains('.') => self.push(Value::new_float(f)),
+                        _ => self.push(Value::new_poison_with("not a number")),
+                    }
+                },
+                "c2n" | "ord" => {
+                    let arg = self.pop_any()?;
+                    let c = match arg.as_char() {
+                        Some(c) => c,
+                        None => match arg.as_slice().ok_or("expected list")? {
+                            [c] => c.as_char().ok_or("expected char")?,
+                            _ => Err("expected single char")?,
+                        },
+                    };
+                    self.push(Value::new_i64(c as i64));
+                },
+                "n2c" => {
+                    let n = self.pop_i64()?;
+                    self.push(Value::new_char(n.try_into().ok().and_then(char::from_u32).ok_or("invalid codepoint")?));
+                },
+                "chr" => {
+                    let n = self.pop_i64()?;
+                    let c = n.try_into().ok().and_then(char::from_u32).ok_or("invalid codepoint")?;
+                    self.push(Value::new_str(&c.to_string()));
+                },
+                "bits" => {
+                    let n = self.pop_as("expected integer", Value::as_num)?;
+                    let (sign, digits) = n.to_radix_be(2);
+                    if sign == Sign::Minus {
+                        Err("expected non-negative integer")?
                     }
+                    self.push(Value::new_list(digits.into_iter().map(|d| Value::new_i64(d as i64)).collect()));
                 },
                 "collect" => {
-                    let arg = self.pop()?;
-                    let cursor = arg.as_quote()?;
+                    let arg = self.pop_any()?;
+                    let cursor = arg.as_quote().ok_or("expected quote")?;
                     let mut vm = self.new_child();
                     vm.eval_cursor(trace, cursor.clone());
                     self.push(Value::new_list(vm.stack))
                 },
                 "each" => {
-                    let list = self.pop()?.as_list()?;
+                    let list = self.pop_list()?;
                     for value in list.into_iter() {
                         self.push(value)
                     }
                 },
                 "reach" => {
-                    let list = self.pop()?.as_list()?;
+                    let list = self.pop_list()?;
                     for value in list.into_iter().rev() {
                         self.push(value)
                     }
                 },
                 "set" => {
-                    let arg = self.pop()?;
-                    let list = arg.as_list()?;
+                    let arg = self.pop_any()?;
+                    let list = arg.as_list().ok_or("expected list")?;
                     self.push(Value::new_set(Polyset::from_vec(list)));
                 },
-                "nub" => {
-                    let set = self.pop()?.as_set()?;
+                "nub" | "sortuniq" => {
+                    let set = self.pop_set()?;
                     self.push(Value::new_list(set.keys().cloned().collect()))
                 },
+                "uniq" => {
+                    let mut list = self.pop_list()?;
+                    list.dedup();
+                    self.push(Value::new_list(list));
+                },
+                "distinct" => {
+                    let list = self.pop_list()?;
+                    let mut seen = BTreeSet::new();
+                    let result = list.into_iter().filter(|value| seen.insert(value.clone()));
+                    self.push(Value::new_list(result.collect()));
+                },
+                "card" => {
+                    let set = self.pop_set()?;
+                    self.push(Value::new_i64(set.total()));
+                },
+                "mult" => {
+                    let key = self.pop_any()?;
+                    let set = self.pop_set()?;
+                    self.push(Value::new_i64(set.get(&key)));
+                },
+                "mostcommon" => {
+                    let k = self.pop_usize()?;
+                    let set = self.pop_set()?;
+                    let mut elems: Vec<_> = set.into_iter().collect();
+                    elems.sort_by(|(x, m), (y, n)| n.cmp(m).then(x.cmp(y)));
+                    let result = elems.into_iter().take(k).map(|(x, n)| Value::new_list(vec![x, Value::new_i64(n)]));
+                    self.push(Value::new_list(result.collect()));
+                },
+                "ndistinct" => {
+                    let set = self.pop_set()?;
+                    self.push(Value::new_i64(set.keys().count() as i64));
+                },
                 "iota" => {
-                    let count = self.pop()?.as_i64()?;
+                    let count = self.pop_i64()?;
                     self.push(Value::new_list((0 .. count).map(Value::new_i64).collect()));
                 },
                 "at" => {
-                    let offset = self.pop()?.as_usize()?;
-                    let list = self.pop()?.as_list()?;
-                    self.push(list.get(offset)?.clone());
+                    let offset = self.pop_usize()?;
+                    let list = self.pop_list()?;
+                    self.push(list.get(offset).ok_or("index out of range")?.clone());
                 },
                 "chunks" => {
-                    let size = self.pop()?.as_usize()?;
-                    let list = self.pop()?.as_list()?;
+                    let size = self.pop_usize()?;
+                    let list = self.pop_list()?;
                     self.push(Value::new_list(list.chunks(size).map(|chunk| Value::new_list(chunk.to_vec())).collect()));
                 },
                 "frames" => {
-                    let size = self.pop()?.as_usize()?;
-                    let list = self.pop()?.as_list()?;
+                    let size = self.pop_usize()?;
+                    let list = self.pop_list()?;
                     self.push(Value::new_list(list.windows(size).map(|vs| Value::new_list(vs.to_vec())).collect()));
                 },
+                "deal" => {
+                    let list = self.pop_list()?;
+                    let (even, odd): (Vec<_>, Vec<_>) = list.into_iter().enumerate().partition(|(i, _)| i % 2 == 0);
+                    self.push(Value::new_list(even.into_iter().map(|(_, v)| v).collect()));
+                    self.push(Value::new_list(odd.into_iter().map(|(_, v)| v).collect()));
+                },
+                "interleave" => {
+                    let b = self.pop_list()?;
+                    let a = self.pop_list()?;
+                    let mut result = Vec::with_capacity(a.len() + b.len());
+                    let mut a = a.into_iter();
+                    let mut b = b.into_iter();
+                    loop {
+                        match (a.next(), b.next()) {
+                            (Some(x), Some(y)) => {
+                                result.push(x);
+                                result.push(y);
+                            },
+                            (Some(x), None) => {
+                                result.push(x);
+                                result.extend(a);
+                                break;
+                            },
+                            (None, Some(y)) => {
+                                result.push(y);
+                                result.extend(b);
+                                break;
+                            },
+                            (None, None) => {
+                                break;
+                            },
+                        }
+                    }
+                    self.push(Value::new_list(result));
+                },
+                "chunksizes" => {
+                    let sizes = self.pop_list()?;
+                    let list = self.pop_list()?;
+                    let mut rest = &list[..];
+                    let mut result = Vec::new();
+                    for size in sizes {
+                        if rest.is_empty() {
+                            break;
+                        }
+                        let (piece, tail) = rest.split_at(size.as_usize().ok_or("expected non-negative integer")?.min(rest.len()));
+                        result.push(Value::new_list(piece.to_vec()));
+                        rest = tail;
+                    }
+                    if !rest.is_empty() {
+                        result.push(Value::new_list(rest.to_vec()));
+                    }
+                    self.push(Value::new_list(result));
+                },
+                "pairs" => {
+                    let list = self.pop_list()?;
+                    self.push(Value::new_list(list.windows(2).map(|pair| Value::new_list(pair.to_vec())).collect()));
+                },
+                "invert" => {
+                    let arg = self.pop_any()?;
+                    let mut result = Vec::new();
+                    for pair in arg.as_slice().ok_or("expected list")? {
+                        let [k, v] = pair.as_slice().ok_or("expected list")? else {
+                            Err("expected pair")?
+                        };
+                        result.push(Value::new_list(vec![v.clone(), k.clone()]));
+                    }
+                    self.push(Value::new_list(result));
+                },
+                "inits" => {
+                    let list = self.pop_list()?;
+                    self.push(Value::new_list((0 ..= list.len()).map(|n| Value::new_list(list[.. n].to_vec())).collect()));
+                },
+                "tails" => {
+                    let list = self.pop_list()?;
+                    self.push(Value::new_list((0 ..= list.len()).map(|n| Value::new_list(list[n ..].to_vec())).collect()));
+                },
+                "intersperse" => {
+                    let separator = self.pop_any()?;
+                    let list = self.pop_list()?;
+                    self.push(Value::new_list(list.into_iter().intersperse(separator).collect()));
+                },
+                "rle" => {
+                    let list = self.pop_list()?;
+                    let mut runs: Vec<(i64, Value)> = Vec::new();
+                    for value in list {
+                        match runs.last_mut() {
+                            Some((count, prev)) if *prev == value => *count += 1,
+                            _ => runs.push((1, value)),
+                        }
+                    }
+                    let result = runs.into_iter().map(|(count, value)| Value::new_list(vec![Value::new_i64(count), value]));
+                    self.push(Value::new_list(result.collect()));
+                },
+                "unrle" => {
+                    let list = self.pop_list()?;
+                    let mut result = Vec::new();
+                    for pair in list {
+                        let [count, value] = pair.as_slice().ok_or("expected list")? else {
+                            Err("expected pair")?
+                        };
+                        result.extend(repeat_n(value.clone(), count.as_usize().ok_or("expected non-negative integer")?));
+                    }
+                    self.push(Value::new_list(result));
+                },
                 "len" => {
-                    let arg = self.pop()?;
-                    self.push(Value::new_i64(arg.as_slice()?.len() as i64));
+                    let arg = self.pop_any()?;
+                    self.push(Value::new_i64(arg.as_slice().ok_or("expected list")?.len() as i64));
                 },
                 "sum" => {
-                    let arg = self.pop()?;
+                    let arg = self.pop_any()?;
                     let result = arg
-                        .as_slice()?
+                        .as_slice().ok_or("expected list")?
                         .iter()
-                        .map(|v| v.as_num())
-                        .reduce(|m, n| Some(m? + n?))??;
-                    self.push(Value::new_num(result));
+                        .map(to_number)
+                        .reduce(|m, n| add(&m?, &n?)).flatten().ok_or("expected non-empty list of numbers")?;
+                    self.push(result);
+                },
+                "cumsum" => {
+                    let arg = self.pop_any()?;
+                    let mut total = Value::new_i64(0);
+                    let mut result = Vec::new();
+                    for value in arg.as_slice().ok_or("expected list")? {
+                        total = add(&total, value).ok_or("expected number")?;
+                        result.push(total.clone());
+                    }
+                    self.push(Value::new_list(result));
+                },
+                "diff" => {
+                    let arg = self.pop_any()?;
+                    let list = arg.as_slice().ok_or("expected list")?;
+                    let result = list.windows(2).map(|pair| sub(&pair[1], &pair[0])).collect::<Option<Vec<_>>>().ok_or("expected list of numbers")?;
+                    self.push(Value::new_list(result));
                 },
                 "product" => {
-                    let arg = self.pop()?;
+                    let arg = self.pop_any()?;
                     let result = arg
-                        .as_slice()?
+                        .as_slice().ok_or("expected list")?
                         .iter()
-                        .map(|v| v.as_num())
-                        .fold(Some(1.into()), |m, n| Some(m? * n?))?;
-                    self.push(Value::new_num(result));
+                        .map(to_number)
+                        .try_fold(Value::new_i64(1), |m, n| mul(&m, &n?)).ok_or("expected list of numbers")?;
+                    self.push(result);
                 },
                 "max" => {
-                    let arg = self.pop()?;
+                    let arg = self.pop_any()?;
                     let result = arg
-                        .as_slice()?
+                        .as_slice().ok_or("expected list")?
                         .iter()
-                        .map(|v| v.as_num())
-                        .reduce(|m, n| Some(m?.max(n?)))??;
-                    self.push(Value::new_num(result));
+                        .map(to_number)
+                        .reduce(|m, n| Some(m?.max(n?))).flatten().ok_or("expected non-empty list of numbers")?;
+                    self.push(result);
+                },
+                "longest" => {
+                    let arg = self.pop_any()?;
+                    let mut best: Option<(&Value, usize)> = None;
+                    for value in arg.as_slice().ok_or("expected list")? {
+                        let len = value.as_slice().ok_or("expected list")?.len();
+                        if best.is_none_or(|(_, best_len)| best_len < len) {
+                            best = Some((value, len));
+                        }
+                    }
+                    self.push(best.ok_or("empty list")?.0.clone());
+                },
+                "maxwindow" => {
+                    let size = self.pop_usize()?;
+                    let arg = self.pop_any()?;
+                    let nums = arg.as_slice().ok_or("expected list")?;
+                    if !nums.iter().all(Value::is_num) {
+                        Err("expected list of numbers")?
+                    }
+                    if size == 0 {
+                        Err("window size zero")?
+                    }
+                    if size > nums.len() {
+                        Err("window larger than list")?
+                    }
+                    let mut window: VecDeque<usize> = VecDeque::new();
+                    let mut result = Vec::new();
+                    for (i, n) in nums.iter().enumerate() {
+                        while window.back().is_some_and(|&j| nums[j] <= *n) {
+                            window.pop_back();
+                        }
+                        window.push_back(i);
+                        if window.front().is_some_and(|&j| j + size <= i) {
+                            window.pop_front();
+                        }
+                        if i + 1 >= size {
+                            result.push(nums[window[0]].clone());
+                        }
+                    }
+                    self.push(Value::new_list(result));
                 },
                 "sort" => {
-                    let mut list = self.pop()?.as_list()?;
+                    let mut list = self.pop_list()?;
                     list.sort();
                     self.push(Value::new_list(list));
                 },
                 "rsort" => {
-                    let mut list = self.pop()?.as_list()?;
+                    let mut list = self.pop_list()?;
                     list.sort_by(|a, b| b.cmp(a));
                     self.push(Value::new_list(list));
                 },
                 "append" => {
-                    let mut b = self.pop()?.as_list()?;
-                    let mut a = self.pop()?.as_list()?;
+                    let mut b = self.pop_list()?;
+                    let mut a = self.pop_list()?;
                     a.append(&mut b);
                     self.push(Value::new_list(a));
                 },
+                "merge" => {
+                    let b = self.pop_list()?;
+                    let a = self.pop_list()?;
+                    let mut result = Vec::with_capacity(a.len() + b.len());
+                    let mut a = a.into_iter().peekable();
+                    let mut b = b.into_iter().peekable();
+                    while let (Some(x), Some(y)) = (a.peek(), b.peek()) {
+                        if x <= y {
+                            result.push(a.next().unwrap());
+                        } else {
+                            result.push(b.next().unwrap());
+                        }
+                    }
+                    result.extend(a);
+                    result.extend(b);
+                    self.push(Value::new_list(result));
+                },
                 "find" => {
-                    let table = self.pop()?.as_list()?;
-                    let needle = self.pop()?;
+                    let table = self.pop_list()?;
+                    let needle = self.pop_any()?;
                     match table.iter().position(|v| *v == needle) {
-                        None => self.push(Value::new_poison()),
+                        None => self.push(Value::new_poison_with("not found")),
+                        Some(i) => self.push(Value::new_i64(i as i64)),
+                    }
+                },
+                "rfind" => {
+                    let table = self.pop_list()?;
+                    let needle = self.pop_any()?;
+                    match table.iter().rposition(|v| *v == needle) {
+                        None => self.push(Value::new_poison_with("not found")),
                         Some(i) => self.push(Value::new_i64(i as i64)),
                     }
                 },
                 "union" => {
-                    let a = self.pop()?.as_set()?;
-                    let b = self.pop()?.as_set()?;
+                    let a = self.pop_set()?;
+                    let b = self.pop_set()?;
                     self.push(Value::new_set(a.union(b)));
                 },
                 "join" => {
-                    let a = self.pop()?.as_set()?;
-                    let b = self.pop()?.as_set()?;
+                    let a = self.pop_set()?;
+                    let b = self.pop_set()?;
                     self.push(Value::new_set(a.join(b)));
                 },
+                "intersect" => {
+                    let a = self.pop_set()?;
+                    let b = self.pop_set()?;
+                    self.push(Value::new_set(a.intersection(b)));
+                },
+                "setdiff" => {
+                    let b = self.pop_set()?;
+                    let a = self.pop_set()?;
+                    self.push(Value::new_set(a.difference(b)));
+                },
+                "overlap" => {
+                    let a = self.pop_set()?;
+                    let b = self.pop_set()?;
+                    self.push(Value::new_i64(a.overlap(&b)));
+                },
                 "map" => {
-                    let arg2 = self.pop()?;
-                    let arg1 = self.pop()?;
-                    let list = arg1.as_list()?;
-                    let cursor = arg2.as_quote()?;
+                    let arg2 = self.pop_any()?;
+                    let arg1 = self.pop_any()?;
+                    let list = arg1.as_list().ok_or("expected list")?;
+                    let cursor = arg2.as_quote().ok_or("expected quote")?;
                     let mut result = Vec::new();
                     for value in list {
                         let mut vm = self.new_child();
@@ -339,29 +923,231 @@ impl VM {
                     }
                     self.push(Value::new_list(result));
                 },
+                "foldr" => {
+                    let arg = self.pop_any()?;
+                    let mut acc = self.pop_any()?;
+                    let list = self.pop_list()?;
+                    let cursor = arg.as_quote().ok_or("expected quote")?;
+                    for value in list.into_iter().rev() {
+                        acc = self.apply(trace, cursor, [value, acc])?;
+                    }
+                    self.push(acc);
+                },
+                "windowmap" => {
+                    let arg = self.pop_any()?;
+                    let size = self.pop_usize()?;
+                    let list = self.pop_list()?;
+                    let cursor = arg.as_quote().ok_or("expected quote")?;
+                    if size == 0 {
+                        Err("window size zero")?
+                    }
+                    let mut result = Vec::new();
+                    for window in list.windows(size) {
+                        result.push(self.apply(trace, cursor, [Value::new_list(window.to_vec())])?);
+                    }
+                    self.push(Value::new_list(result));
+                },
+                "pairwise" => {
+                    let arg = self.pop_any()?;
+                    let list = self.pop_list()?;
+                    let cursor = arg.as_quote().ok_or("expected quote")?;
+                    let mut result = Vec::new();
+                    for pair in list.windows(2) {
+                        result.push(self.apply(trace, cursor, [pair[0].clone(), pair[1].clone()])?);
+                    }
+                    self.push(Value::new_list(result));
+                },
+                "groupbykey" => {
+                    let arg = self.pop_any()?;
+                    let list = self.pop_list()?;
+                    let cursor = arg.as_quote().ok_or("expected quote")?;
+                    let mut indices = BTreeMap::new();
+                    let mut groups: Vec<Vec<Value>> = Vec::new();
+                    for value in list {
+                        let key = self.apply(trace, cursor, [value.clone()])?;
+                        let index = *indices.entry(key).or_insert_with(|| {
+                            groups.push(Vec::new());
+                            groups.len() - 1
+                        });
+                        groups[index].push(value);
+                    }
+                    self.push(Value::new_list(groups.into_iter().map(Value::new_list).collect()));
+                },
+                "uniqueby" => {
+                    let arg = self.pop_any()?;
+                    let list = self.pop_list()?;
+                    let cursor = arg.as_quote().ok_or("expected quote")?;
+                    let mut seen = BTreeSet::new();
+                    let mut result = Vec::new();
+                    for value in list {
+                        if seen.insert(self.apply(trace, cursor, [value.clone()])?) {
+                            result.push(value);
+                        }
+                    }
+                    self.push(Value::new_list(result));
+                },
+                "chunkwhile" => {
+                    let arg = self.pop_any()?;
+                    let list = self.pop_list()?;
+                    let cursor = arg.as_quote().ok_or("expected quote")?;
+                    let mut result = Vec::new();
+                    let mut chunk: Vec<Value> = Vec::new();
+                    for value in list {
+                        if let Some(prev) = chunk.last() {
+                            if !self.apply(trace, cursor, [prev.clone(), value.clone()])?.as_bool().ok_or("expected boolean")? {
+                                result.push(Value::new_list(mem::take(&mut chunk)));
+                            }
+                        }
+                        chunk.push(value);
+                    }
+                    if !chunk.is_empty() {
+                        result.push(Value::new_list(chunk));
+                    }
+                    self.push(Value::new_list(result));
+                },
+                "countif" => {
+                    let arg = self.pop_any()?;
+                    let list = self.pop_list()?;
+                    let cursor = arg.as_quote().ok_or("expected quote")?;
+                    let mut count = 0;
+                    for value in list {
+                        if self.apply(trace, cursor, [value])?.as_bool().ok_or("expected boolean")? {
+                            count += 1;
+                        }
+                    }
+                    self.push(Value::new_i64(count));
+                },
+                "withprev" => {
+                    let arg = self.pop_any()?;
+                    let list = self.pop_list()?;
+                    let cursor = arg.as_quote().ok_or("expected quote")?;
+                    let mut result = Vec::new();
+                    // The first element has no predecessor and is paired with itself.
+                    for (i, value) in list.iter().enumerate() {
+                        let prev = list[i.saturating_sub(1)].clone();
+                        result.push(self.apply(trace, cursor, [prev, value.clone()])?);
+                    }
+                    self.push(Value::new_list(result));
+                },
+                "span" => {
+                    let arg2 = self.pop_any()?;
+                    let mut list = self.pop_list()?;
+                    let cursor = arg2.as_quote().ok_or("expected quote")?;
+                    let mut index = list.len();
+                    for (i, value) in list.iter().enumerate() {
+                        if !self.apply(trace, cursor, [value.clone()])?.as_bool().ok_or("expected boolean")? {
+                            index = i;
+                            break;
+                        }
+                    }
+                    let rest = list.split_off(index);
+                    self.push(Value::new_list(list));
+                    self.push(Value::new_list(rest));
+                },
+                "power" => {
+                    let count = self.pop_usize()?;
+                    let arg = self.pop_any()?;
+                    let cursor = arg.as_quote().ok_or("expected quote")?;
+                    let mut value = self.pop_any()?;
+                    if count > self.max_iterations {
+                        Err("iteration limit exceeded")?
+                    }
+                    for _ in 0 .. count {
+                        value = self.apply(trace, cursor, [value])?;
+                    }
+                    self.push(value);
+                },
+                "fixpoint" => {
+                    let arg = self.pop_any()?;
+                    let cursor = arg.as_quote().ok_or("expected quote")?;
+                    let mut value = self.pop_any()?;
+                    let mut stable = false;
+                    for _ in 0 .. self.max_iterations {
+                        let next = self.apply(trace, cursor, [value.clone()])?;
+                        if next == value {
+                            stable = true;
+                            break;
+                        }
+                        value = next;
+                    }
+                    if !stable {
+                        Err("iteration limit exceeded")?
+                    }
+                    self.push(value);
+                },
+                "unfold" => {
+                    let arg = self.pop_any()?;
+                    let cursor = arg.as_quote().ok_or("expected quote")?;
+                    let mut state = self.pop_any()?;
+                    let mut result = Vec::new();
+                    loop {
+                        if result.len() >= self.max_iterations {
+                            Err("iteration limit exceeded")?
+                        }
+                        let step = self.apply(trace, cursor, [state])?;
+                        if step.is_poison() {
+                            break;
+                        }
+                        let [value, next] = step.as_slice().ok_or("expected list")? else {
+                            Err("expected pair")?
+                        };
+                        result.push(value.clone());
+                        state = next.clone();
+                    }
+                    self.push(Value::new_list(result));
+                },
                 "under" => {
-                    let count = self.pop()?.as_usize()?;
-                    let cursor = self.pop()?.as_quote()?.clone();
+                    let count = self.pop_usize()?;
+                    let cursor = self.pop_any()?.as_quote().ok_or("expected quote")?.clone();
                     let index = self.stack.len() - count;
                     if index > self.stack.len() {
-                        None?
+                        Err("empty stack")?
                     }
                     let mut temp = self.stack.split_off(index);
                     self.eval_cursor(trace, cursor);
                     self.stack.append(&mut temp);
                 },
                 "shape" => {
-                    let arg = self.pop()?;
+                    let arg = self.pop_any()?;
                     self.push(arg.shape().repr());
                 },
+                "coords" => {
+                    let rows = self.pop_list()?;
+                    let mut result = Vec::new();
+                    for (i, row) in rows.iter().enumerate() {
+                        for (j, value) in row.as_slice().ok_or("expected list")?.iter().enumerate() {
+                            result.push(Value::new_list(vec![Value::new_i64(i as i64), Value::new_i64(j as i64), value.clone()]));
+                        }
+                    }
+                    self.push(Value::new_list(result));
+                },
+                "isrect" => {
+                    let rows = self.pop_list()?;
+                    let lens = rows.iter().map(|row| Some(row.as_slice()?.len())).collect::<Option<Vec<_>>>().ok_or("expected list of lists")?;
+                    self.push(Value::new_bool(lens.windows(2).all(|w| w[0] == w[1])));
+                },
+                "sameshape" => {
+                    let b = self.pop_any()?;
+                    let a = self.pop_any()?;
+                    self.push(Value::new_bool(a.shape() == b.shape()));
+                },
+                "prompt" => {
+                    let prompt = self.pop_string()?;
+                    let line = self.input.borrow_mut().read_line(prompt).ok_or("awaiting input")?;
+                    self.push(Value::new_str(&line));
+                },
+                "now" => {
+                    let millis = (self.clock)().ok_or("clock unavailable")?;
+                    self.push(Value::new_i64(millis));
+                },
                 _ => {
-                    None?;
+                    Err("unknown primitive")?;
                 },
             }
         };
         match result {
-            None => self.push(Value::new_poison()),
-            Some(()) => (),
+            Err(reason) => self.push(Value::new_poison_with(reason)),
+            Ok(()) => (),
         }
     }
 
@@ -369,6 +1155,7 @@ impl VM {
         self.add_snapshot(trace, cursor.shape());
         while let Some(expr) = cursor.next_expr().cloned() {
             cursor.move_right();
+            self.step = Some(cursor.shape());
             match expr {
                 Expr::Ident(prim) => {
                     self.eval_prim(trace, prim.as_str());
@@ -376,10 +1163,17 @@ impl VM {
                 Expr::StrLit(s) => {
                     self.push(Value::new_str(&s));
                 },
+                Expr::CharLit(c) => {
+                    self.push(Value::new_char(c));
+                },
                 Expr::NumLit(n) => {
                     self.push(Value::new_num(n.clone()));
                 },
+                Expr::FloatLit(Float(f)) => {
+                    self.push(Value::new_float(f));
+                },
                 Expr::Quote(_) => {
+                    // The quote owns a deep copy of the program, so later edits never reach captured values.
                     let mut quote_cursor = cursor.clone();
                     quote_cursor.move_up();
                     quote_cursor.move_very_left();
@@ -391,17 +1185,407 @@ impl VM {
     }
 }
 
-impl Pretty for VM {
-    fn layout(&self) -> Layout {
-        let layout = Layout::VConcat(self.stack.iter().enumerate().map(|(index, item)| {
-            let offset = self.stack.len() - index - 1;
-            let header = Layout::ExactWidth(Box::new(Layout::mk_text(Color::Cyan, Color::Black, &format!("{offset}"))), 4);
-            Layout::Diminish(Box::new(Layout::HConcat(vec![header, item.layout()])))
-        }).collect());
+impl VM {
+    fn entry_layout(header: &str, color: Color, item: &Value, options: TextOptions) -> Layout {
+        let header = Layout::ExactWidth(Box::new(Layout::mk_text(color, Color::Black, header)), 4);
+        Layout::Diminish(Box::new(Layout::HConcat(vec![header, item.layout_with(options)])))
+    }
+
+    fn with_parent_layout(&self, layout: Layout, options: TextOptions) -> Layout {
         if let Some(parent) = &self.parent {
-            Layout::VConcat(vec![parent.layout(), layout])
+            Layout::VConcat(vec![parent.layout_with(options), layout])
         } else {
             layout
         }
     }
+
+    pub fn layout_with(&self, options: TextOptions) -> Layout {
+        let layout = Layout::VConcat(self.stack.iter().enumerate().map(|(index, item)| {
+            let offset = self.stack.len() - index - 1;
+            Self::entry_layout(&format!("{offset}"), Color::Cyan, item, options)
+        }).collect());
+        self.with_parent_layout(layout, options)
+    }
+
+    pub fn diff_layout(&self, before: &VM, options: TextOptions) -> Layout {
+        let common = self.stack.iter().zip(before.stack.iter()).take_while(|(a, b)| a == b).count();
+        let mut entries = Vec::new();
+        for (index, item) in self.stack[.. common].iter().enumerate() {
+            let offset = self.stack.len() - index - 1;
+            entries.push(Self::entry_layout(&format!("{offset}"), Color::Cyan, item, options));
+        }
+        for item in &before.stack[common ..] {
+            entries.push(Self::entry_layout("-", Color::Red, item, options));
+        }
+        for (index, item) in self.stack.iter().enumerate().skip(common) {
+            let offset = self.stack.len() - index - 1;
+            entries.push(Self::entry_layout(&format!("{offset}"), Color::Green, item, options));
+        }
+        self.with_parent_layout(Layout::VConcat(entries), options)
+    }
+
+    pub fn plain_text(&self, options: TextOptions) -> String {
+        let mut out = match &self.parent {
+            Some(parent) => parent.plain_text(options),
+            None => String::new(),
+        };
+        for (index, item) in self.stack.iter().enumerate() {
+            let offset = self.stack.len() - index - 1;
+            out.push_str(&format!("{offset:<4}{}\n", item.plain_text(options)));
+        }
+        out
+    }
+}
+
+impl Pretty for VM {
+    fn layout(&self) -> Layout {
+        self.layout_with(TextOptions::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::{self, parse};
+
+    fn run(source: &str) -> Vec<String> {
+        let mut vm = VM::with_input(Vec::new());
+        vm.eval_cursor(&mut HashMap::new(), Cursor::initial(parse(source).unwrap()));
+        vm.stack.iter().map(|value| value.plain_text(TextOptions::default())).collect()
+    }
+
+    #[test]
+    fn power_respects_iteration_limit() {
+        let mut vm = VM::with_input(Vec::new()).with_max_iterations(10);
+        vm.eval_cursor(&mut HashMap::new(), Cursor::initial(parse("0 {inc} 11 power").unwrap()));
+        assert_eq!(vm.stack, [Value::new_poison_with("iteration limit exceeded")]);
+        assert_eq!(run("0 {inc} 11 power"), ["11"]);
+    }
+
+    #[test]
+    fn division_by_false_is_division_by_zero() {
+        assert_eq!(run("1 0 1 == /"), ["☠(division by zero)"]);
+    }
+
+    #[test]
+    fn bools_equal_their_numbers() {
+        assert_eq!(run("1 1 == 1 =="), ["true"]);
+        assert_eq!(run("2 2 == {0 1 2} collect find"), ["1"]);
+    }
+
+    #[test]
+    fn floats_order_numerically() {
+        assert_eq!(run("{2.5 1 3} collect sort"), ["[1 2.5 3]"]);
+        assert_eq!(run("{1 2} collect {1.5} collect merge"), ["[1 1.5 2]"]);
+        assert_eq!(run("{1} collect {0.5} collect cmp"), ["[1]"]);
+        assert_eq!(run("1 1.0 =="), ["true"]);
+    }
+
+    #[test]
+    fn list_arithmetic_promotes_to_float() {
+        assert_eq!(run("{0.5 1 2.5} collect sum"), ["4.0"]);
+        assert_eq!(run("{0.5 1 2} collect product"), ["1.0"]);
+        assert_eq!(run("{0.5 1 2.5} collect max"), ["2.5"]);
+        assert_eq!(run("{0.5 1 2.5} collect cumsum"), ["[0.5 1.5 4.0]"]);
+        assert_eq!(run("{0.5 1 2.5} collect diff"), ["[0.5 1.5]"]);
+        assert_eq!(run("{1 2 3} collect sum"), ["6"]);
+        assert_eq!(run("{\"a\"} collect sum"), ["☠(expected non-empty list of numbers)"]);
+        assert_eq!(run("{{1 2} collect} collect sum"), ["☠(expected non-empty list of numbers)"]);
+        assert_eq!(run("{1 1 ==} collect sum"), ["1"]);
+        assert_eq!(run("{1 1 ==} collect max"), ["1"]);
+        assert_eq!(run("{\"a\"} collect product"), ["☠(expected list of numbers)"]);
+        assert_eq!(run("1.5 1 2 between"), ["true"]);
+        assert_eq!(run("\"2.5\" 2.5 eqnum"), ["true"]);
+        assert_eq!(run("2.5 bits"), ["☠(expected integer)"]);
+    }
+
+    #[test]
+    fn captured_quotes_are_snapshots() {
+        let mut cursor = Cursor::initial(parse("{1 2 +}").unwrap());
+        let mut vm = VM::with_input(Vec::new());
+        vm.eval_cursor(&mut HashMap::new(), cursor.clone());
+        let quote = vm.stack[0].clone();
+        cursor.move_right();
+        cursor.move_up();
+        cursor.insert(Cursor::Edge(vec![Expr::NumLit(10.into()), Expr::Ident("*".to_string())], Vec::new()));
+        assert_eq!(syntax::to_source(&cursor.program()), "{1 2 + 10 *}");
+        let result = vm.apply(&mut HashMap::new(), quote.as_quote().unwrap(), []);
+        assert_eq!(result, Ok(Value::new_i64(3)));
+    }
+
+    #[test]
+    fn now_reads_injected_clock() {
+        let mut vm = VM::with_input(Vec::new()).with_clock(|| Some(1234));
+        vm.eval_cursor(&mut HashMap::new(), Cursor::initial(parse("now now").unwrap()));
+        assert_eq!(vm.stack, [Value::new_i64(1234), Value::new_i64(1234)]);
+    }
+
+    #[test]
+    fn num_parses_signs_and_prefixes() {
+        assert_eq!(run("\"-42\" num"), ["-42"]);
+        assert_eq!(run("\"0xff\" num"), ["255"]);
+        assert_eq!(run("\"-0b101\" num"), ["-5"]);
+        assert_eq!(run("\"abc\" num"), ["☠(not a number)"]);
+        assert_eq!(run("\"1_000\" num"), ["☠(not a number)"]);
+    }
+
+    #[test]
+    fn ndistinct_counts_distinct_values() {
+        assert_eq!(run("{1 1 2 3 3} collect ndistinct"), ["3"]);
+        assert_eq!(run("{} collect ndistinct"), ["0"]);
+    }
+
+    #[test]
+    fn span_splits_at_first_failure() {
+        assert_eq!(run("{1 2 3 4 1} collect {2 =<} span"), ["[1 2]", "[3 4 1]"]);
+    }
+
+    #[test]
+    fn merge_interleaves_sorted_lists() {
+        assert_eq!(run("{1 3 5} collect {2 4 6} collect merge"), ["[1 2 3 4 5 6]"]);
+    }
+
+    #[test]
+    fn char_arithmetic_round_trips() {
+        assert_eq!(run("\"a\" 0 at c2n inc n2c"), ["b"]);
+    }
+
+    #[test]
+    fn power_composes_quote() {
+        assert_eq!(run("2 {dup *} 3 power"), ["256"]);
+    }
+
+    #[test]
+    fn deal_splits_alternately() {
+        assert_eq!(run("{1 2 3 4 5} collect deal"), ["[1 3 5]", "[2 4]"]);
+    }
+
+    #[test]
+    fn interleave_alternates() {
+        assert_eq!(run("{1 3 5} collect {2 4} collect interleave"), ["[1 2 3 4 5]"]);
+    }
+
+    #[test]
+    fn cumsum_accumulates() {
+        assert_eq!(run("{1 2 3 4} collect cumsum"), ["[1 3 6 10]"]);
+    }
+
+    #[test]
+    fn prompt_reads_injected_input() {
+        let mut vm = VM::with_input(vec!["hello".to_string()]);
+        vm.eval_cursor(&mut HashMap::new(), Cursor::initial(parse("\"name?\" prompt \"again?\" prompt").unwrap()));
+        assert_eq!(vm.stack, [Value::new_str("hello"), Value::new_poison_with("awaiting input")]);
+        assert_eq!(vm.pending_prompt().as_deref(), Some("again?"));
+    }
+
+    #[test]
+    fn clear_empties_stack() {
+        assert!(run("1 2 3 clear").is_empty());
+    }
+
+    #[test]
+    fn pick_copies_from_top() {
+        assert_eq!(run("1 2 3 0 pick"), ["1", "2", "3", "3"]);
+        assert_eq!(run("1 2 3 5 pick"), ["1", "2", "3", "☠(index out of range)"]);
+        assert_eq!(run("1 2 3 18446744073709551615 pick"), ["1", "2", "3", "☠(index out of range)"]);
+    }
+
+    #[test]
+    fn cmp_compares_elementwise() {
+        assert_eq!(run("{1 2 3} collect {1 5 2} collect cmp"), ["[0 -1 1]"]);
+    }
+
+    #[test]
+    fn diff_takes_differences() {
+        assert_eq!(run("{1 3 6 10} collect diff"), ["[2 3 4]"]);
+    }
+
+    #[test]
+    fn windowmap_maps_windows() {
+        assert_eq!(run("{1 2 3 4} collect 2 {sum} windowmap"), ["[3 5 7]"]);
+    }
+
+    #[test]
+    fn groupbykey_groups_by_key() {
+        assert_eq!(run("{1 2 3 4} collect {dup 2 / -2 * +} groupbykey"), ["[[1 3] [2 4]]"]);
+    }
+
+    #[test]
+    fn fixpoint_iterates_until_stable() {
+        assert_eq!(run("{1 1 2 2 2 3 3} collect {uniq} fixpoint"), ["[1 2 3]"]);
+    }
+
+    #[test]
+    fn unfold_counts_down() {
+        assert_eq!(run("5 {dup dup / * dup -1 + flip irange rsort} unfold"), ["[5 4 3 2 1]"]);
+    }
+
+    #[test]
+    fn chunksizes_splits_by_sizes() {
+        assert_eq!(run("{1 2 3 4 5} collect {2 1 2} collect chunksizes"), ["[[1 2] [3] [4 5]]"]);
+    }
+
+    #[test]
+    fn pairs_lists_adjacent_pairs() {
+        assert_eq!(run("{1 2 3 4} collect pairs"), ["[[1 2] [2 3] [3 4]]"]);
+    }
+
+    #[test]
+    fn rfind_finds_last_occurrence() {
+        assert_eq!(run("1 {1 2 1 3 1} collect rfind"), ["4"]);
+        assert_eq!(run("5 {1 2 1 3 1} collect rfind"), ["☠(not found)"]);
+    }
+
+    #[test]
+    fn countif_counts_matches() {
+        assert_eq!(run("{1 2 3 4 5 6} collect {dup 2 / -2 * + 0 ==} countif"), ["3"]);
+    }
+
+    #[test]
+    fn between_is_inclusive() {
+        assert_eq!(run("5 0 10 between"), ["true"]);
+        assert_eq!(run("15 0 10 between"), ["false"]);
+        assert_eq!(run("10 0 10 between"), ["true"]);
+    }
+
+    #[test]
+    fn swapcase_swaps_case() {
+        assert_eq!(run("\"Hello World\" swapcase"), ["hELLO wORLD"]);
+    }
+
+    #[test]
+    fn title_capitalizes_words() {
+        assert_eq!(run("\"hello world\" title"), ["Hello World"]);
+    }
+
+    #[test]
+    fn indexof_finds_substring() {
+        assert_eq!(run("\"hello\" \"ll\" indexof"), ["2"]);
+        assert_eq!(run("\"hello\" \"z\" indexof"), ["☠(not found)"]);
+    }
+
+    #[test]
+    fn strrepeat_repeats() {
+        assert_eq!(run("\"ab\" 3 strrepeat"), ["ababab"]);
+    }
+
+    #[test]
+    fn overlap_counts_common_elements() {
+        assert_eq!(run("{1 1 2 3} collect {1 2 2} collect overlap"), ["2"]);
+    }
+
+    #[test]
+    fn sameshape_compares_shapes() {
+        assert_eq!(run("{1 2} collect {3 4} collect sameshape"), ["true"]);
+        assert_eq!(run("{1 2} collect {1 \"a\"} collect sameshape"), ["false"]);
+    }
+
+    #[test]
+    fn deepeq_differs_from_eq() {
+        assert_eq!(run("1 0 / 1 0 / =="), ["true"]);
+        assert_eq!(run("1 0 / 1 0 / deepeq"), ["false"]);
+        let a = Value::new_quote(Cursor::Edge(parse("1").unwrap(), parse("2").unwrap()));
+        let b = Value::new_quote(Cursor::Edge(Vec::new(), parse("1 2").unwrap()));
+        assert_ne!(a, b);
+        assert!(a.deep_eq(&b));
+    }
+
+    #[test]
+    fn invert_swaps_pairs() {
+        assert_eq!(run("{ {1 \"a\"} collect {2 \"b\"} collect } collect invert"), ["[[a 1] [b 2]]"]);
+    }
+
+    #[test]
+    fn inits_lists_prefixes() {
+        assert_eq!(run("{1 2 3} collect inits"), ["[[] [1] [1 2] [1 2 3]]"]);
+    }
+
+    #[test]
+    fn longest_picks_longest() {
+        assert_eq!(run("{\"a\" \"ccc\" \"bb\"} collect longest"), ["ccc"]);
+    }
+
+    #[test]
+    fn withprev_passes_neighbours() {
+        assert_eq!(run("{1 2 3 4} collect {del} withprev"), ["[1 1 2 3]"]);
+        assert_eq!(run("{1 2 3 4} collect {flip del} withprev"), ["[1 2 3 4]"]);
+    }
+
+    #[test]
+    fn uniqueby_keeps_first_per_key() {
+        assert_eq!(run("{1 2 3 4} collect {dup 2 / -2 * +} uniqueby"), ["[1 2]"]);
+    }
+
+    #[test]
+    fn chunkwhile_groups_runs() {
+        assert_eq!(run("{1 2 3 2 5} collect {=<} chunkwhile"), ["[[1 2 3] [2 5]]"]);
+    }
+
+    #[test]
+    fn diff_layout_marks_popped_and_pushed() {
+        let eval = |source: &str| {
+            let mut vm = VM::with_input(Vec::new());
+            vm.eval_cursor(&mut HashMap::new(), Cursor::initial(parse(source).unwrap()));
+            vm
+        };
+        let layout = eval("0 1 2 +").diff_layout(&eval("0 1 2"), TextOptions::default());
+        let rows: Vec<String> = layout.render(crate::pretty::Size { width: 5, height: 4 }).into_iter().map(|row| row.into_iter().collect()).collect();
+        assert_eq!(rows, ["1   0", "-   1", "-   2", "0   3"]);
+    }
+
+    #[test]
+    fn maxwindow_takes_window_maxima() {
+        assert_eq!(run("{1 3 -1 -3 5 3} collect 3 maxwindow"), ["[3 3 5 5]"]);
+        assert_eq!(run("{1 3} collect 0 maxwindow"), ["☠(window size zero)"]);
+        assert_eq!(run("{1 3} collect 3 maxwindow"), ["☠(window larger than list)"]);
+    }
+
+    #[test]
+    fn poison_carries_reason() {
+        assert_eq!(run("1 0 /"), ["☠(division by zero)"]);
+        assert_eq!(run("\"x\" num"), ["☠(not a number)"]);
+    }
+
+    #[test]
+    fn foldr_folds_from_the_right() {
+        assert_eq!(run("{\"a\" \"b\" \"c\"} collect \"\" {flip append} foldr"), ["cba"]);
+    }
+
+    #[test]
+    fn eqnum_coerces_strings() {
+        assert_eq!(run("\"5\" num 5 =="), ["true"]);
+        assert_eq!(run("\"5\" 5 =="), ["false"]);
+        assert_eq!(run("\"5\" 5 eqnum"), ["true"]);
+    }
+
+    #[test]
+    fn isrect_detects_ragged_lists() {
+        assert_eq!(run("{ {1 2} collect {3 4} collect } collect isrect"), ["true"]);
+        assert_eq!(run("{ {1} collect {2 3} collect } collect isrect"), ["false"]);
+    }
+
+    #[test]
+    fn coords_enumerates_grid() {
+        assert_eq!(run("{ {\"a\" \"b\"} collect } collect coords"), ["[[0 0 a] [0 1 b]]"]);
+    }
+
+    #[test]
+    fn bits_lists_binary_digits() {
+        assert_eq!(run("5 bits"), ["[1 0 1]"]);
+    }
+
+    #[test]
+    fn wordstats_counts_words() {
+        assert_eq!(run("\"a b a c b a\" wordstats"), ["⟨a:3 b:2 c⟩"]);
+    }
+
+    #[test]
+    fn sortuniq_sorts_and_dedups() {
+        assert_eq!(run("{3 1 2 3 1} collect sortuniq"), ["[1 2 3]"]);
+    }
+
+    #[test]
+    fn pairwise_applies_to_adjacent_pairs() {
+        assert_eq!(run("{1 2 3 4} collect {+} pairwise"), ["[3 5 7]"]);
+    }
 }
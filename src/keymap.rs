@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use terminal::{KeyEvent, KeyCode, KeyModifiers};
+use crate::editor::Mode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Command {
+    MoveLeft,
+    MoveRight,
+    MoveWordLeft,
+    MoveWordRight,
+    SwapLeft,
+    SwapRight,
+    MoveVeryLeft,
+    MoveVeryRight,
+    MoveUp,
+    MoveDown,
+    MoveOut,
+    JumpMatching,
+    DeleteBefore,
+    DeleteAfter,
+    DeleteChar,
+    InsertIdent,
+    InsertNumLit,
+    InsertStrLit,
+    InsertCharLit,
+    InsertQuote,
+    WrapInQuote,
+    WrapAllInQuote,
+    UnwrapQuote,
+    EscapeToNormal,
+    OpenPalette,
+    CopyProgram,
+    YankExpr,
+    PasteProgram,
+    PasteAsQuote,
+    AnswerPrompt,
+    ResetAnswers,
+    DumpLayout,
+    ToggleStrings,
+    LongerLists,
+    ShorterLists,
+    ExportTrace,
+    ToggleDiff,
+    ToggleStepInto,
+    SwitchRegister(usize),
+    Undo,
+    Redo,
+    MoreIterations,
+    FewerIterations,
+}
+
+#[derive(Debug, Clone)]
+pub struct KeyMap {
+    bindings: HashMap<(Mode, KeyCode, KeyModifiers), Command>,
+}
+
+impl Command {
+    pub const ALL: &'static [Command] = &[
+        Self::MoveLeft,
+        Self::MoveRight,
+        Self::MoveWordLeft,
+        Self::MoveWordRight,
+        Self::SwapLeft,
+        Self::SwapRight,
+        Self::MoveVeryLeft,
+        Self::MoveVeryRight,
+        Self::MoveUp,
+        Self::MoveDown,
+        Self::MoveOut,
+        Self::JumpMatching,
+        Self::DeleteBefore,
+        Self::DeleteAfter,
+        Self::DeleteChar,
+        Self::InsertIdent,
+        Self::InsertNumLit,
+        Self::InsertStrLit,
+        Self::InsertCharLit,
+        Self::InsertQuote,
+        Self::WrapInQuote,
+        Self::WrapAllInQuote,
+        Self::UnwrapQuote,
+        Self::EscapeToNormal,
+        Self::OpenPalette,
+        Self::CopyProgram,
+        Self::YankExpr,
+        Self::PasteProgram,
+        Self::PasteAsQuote,
+        Self::AnswerPrompt,
+        Self::ResetAnswers,
+        Self::DumpLayout,
+        Self::ToggleStrings,
+        Self::LongerLists,
+        Self::ShorterLists,
+        Self::ExportTrace,
+        Self::ToggleDiff,
+        Self::ToggleStepInto,
+        Self::SwitchRegister(0),
+        Self::SwitchRegister(1),
+        Self::SwitchRegister(2),
+        Self::SwitchRegister(3),
+        Self::SwitchRegister(4),
+        Self::SwitchRegister(5),
+        Self::SwitchRegister(6),
+        Self::SwitchRegister(7),
+        Self::SwitchRegister(8),
+        Self::Undo,
+        Self::Redo,
+        Self::MoreIterations,
+        Self::FewerIterations,
+    ];
+
+    const REGISTER_NAMES: [&'static str; 9] = [
+        "register-1", "register-2", "register-3", "register-4", "register-5",
+        "register-6", "register-7", "register-8", "register-9",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::MoveLeft => "move-left",
+            Self::MoveRight => "move-right",
+            Self::MoveWordLeft => "move-word-left",
+            Self::MoveWordRight => "move-word-right",
+            Self::SwapLeft => "swap-left",
+            Self::SwapRight => "swap-right",
+            Self::MoveVeryLeft => "move-very-left",
+            Self::MoveVeryRight => "move-very-right",
+            Self::MoveUp => "move-up",
+            Self::MoveDown => "move-down",
+            Self::MoveOut => "move-out",
+            Self::JumpMatching => "jump-matching",
+            Self::DeleteBefore => "delete-before",
+            Self::DeleteAfter => "delete-after",
+            Self::DeleteChar => "delete-char",
+            Self::InsertIdent => "insert-ident",
+            Self::InsertNumLit => "insert-num",
+            Self::InsertStrLit => "insert-str",
+            Self::InsertCharLit => "insert-char",
+            Self::InsertQuote => "insert-quote",
+            Self::WrapInQuote => "wrap-in-quote",
+            Self::WrapAllInQuote => "wrap-all-in-quote",
+            Self::UnwrapQuote => "unwrap-quote",
+            Self::EscapeToNormal => "escape",
+            Self::OpenPalette => "palette",
+            Self::CopyProgram => "copy-program",
+            Self::YankExpr => "yank-expr",
+            Self::PasteProgram => "paste-program",
+            Self::PasteAsQuote => "paste-as-quote",
+            Self::AnswerPrompt => "answer-prompt",
+            Self::ResetAnswers => "reset-answers",
+            Self::DumpLayout => "dump-layout",
+            Self::ToggleStrings => "toggle-strings",
+            Self::LongerLists => "longer-lists",
+            Self::ShorterLists => "shorter-lists",
+            Self::ExportTrace => "export-trace",
+            Self::ToggleDiff => "toggle-diff",
+            Self::ToggleStepInto => "toggle-step-into",
+            Self::SwitchRegister(n) => Self::REGISTER_NAMES[*n],
+            Self::Undo => "undo",
+            Self::Redo => "redo",
+            Self::MoreIterations => "more-iterations",
+            Self::FewerIterations => "fewer-iterations",
+        }
+    }
+
+    pub fn is_edit(&self) -> bool {
+        matches!(
+            self,
+            Self::SwapLeft | Self::SwapRight | Self::DeleteBefore | Self::DeleteAfter | Self::DeleteChar
+                | Self::InsertIdent | Self::InsertNumLit | Self::InsertStrLit | Self::InsertCharLit | Self::InsertQuote
+                | Self::WrapInQuote | Self::WrapAllInQuote | Self::UnwrapQuote | Self::PasteProgram | Self::PasteAsQuote
+        )
+    }
+
+    pub fn from_name(name: &str) -> Option<Command> {
+        Self::ALL.iter().copied().find(|command| command.name() == name)
+    }
+}
+
+pub fn fuzzy_find<'a>(query: &str, names: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut matches: Vec<_> = names
+        .into_iter()
+        .filter(|name| {
+            let mut chars = name.chars();
+            query.chars().all(|c| chars.any(|d| c == d))
+        })
+        .collect();
+    matches.sort_by_key(|name| (*name != query, !name.starts_with(query), name.len()));
+    matches
+}
+
+impl KeyMap {
+    pub fn new() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+
+    pub fn bind(&mut self, mode: Mode, code: KeyCode, modifiers: KeyModifiers, command: Command) {
+        self.bindings.insert((mode, code, modifiers), command);
+    }
+
+    pub fn lookup(&self, mode: &Mode, event: &KeyEvent) -> Option<Command> {
+        self.bindings.get(&(mode.clone(), event.code, event.modifiers)).copied()
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        let mut keymap = Self::new();
+        let none = KeyModifiers::empty();
+        for (code, command) in [
+            (KeyCode::Left, Command::MoveLeft),
+            (KeyCode::Right, Command::MoveRight),
+            (KeyCode::Home, Command::MoveVeryLeft),
+            (KeyCode::End, Command::MoveVeryRight),
+            (KeyCode::Up, Command::MoveUp),
+            (KeyCode::Down, Command::MoveOut),
+            (KeyCode::Char('}'), Command::MoveOut),
+            (KeyCode::Tab, Command::JumpMatching),
+            (KeyCode::Backspace, Command::DeleteBefore),
+            (KeyCode::Delete, Command::DeleteAfter),
+            (KeyCode::Char('i'), Command::InsertIdent),
+            (KeyCode::Char('n'), Command::InsertNumLit),
+            (KeyCode::Char('"'), Command::InsertStrLit),
+            (KeyCode::Char('\''), Command::InsertCharLit),
+            (KeyCode::Char('{'), Command::InsertQuote),
+            (KeyCode::Char('w'), Command::WrapInQuote),
+            (KeyCode::Char('W'), Command::WrapAllInQuote),
+            (KeyCode::Char('u'), Command::UnwrapQuote),
+            (KeyCode::Char(':'), Command::OpenPalette),
+            (KeyCode::Char('Y'), Command::CopyProgram),
+            (KeyCode::Char('y'), Command::YankExpr),
+            (KeyCode::Char('P'), Command::PasteProgram),
+            (KeyCode::Char('Q'), Command::PasteAsQuote),
+            (KeyCode::Char('!'), Command::AnswerPrompt),
+            (KeyCode::Char('D'), Command::DumpLayout),
+            (KeyCode::Char('S'), Command::ToggleStrings),
+            (KeyCode::Char('>'), Command::LongerLists),
+            (KeyCode::Char('<'), Command::ShorterLists),
+            (KeyCode::Char('T'), Command::ExportTrace),
+            (KeyCode::Char('d'), Command::ToggleDiff),
+            (KeyCode::Char('s'), Command::ToggleStepInto),
+            (KeyCode::Char('+'), Command::MoreIterations),
+            (KeyCode::Char('-'), Command::FewerIterations),
+        ] {
+            keymap.bind(Mode::Normal, code, none, command);
+        }
+        for (n, c) in ('1' ..= '9').enumerate() {
+            keymap.bind(Mode::Normal, KeyCode::Char(c), none, Command::SwitchRegister(n));
+        }
+        keymap.bind(Mode::Normal, KeyCode::Left, KeyModifiers::CONTROL, Command::MoveWordLeft);
+        keymap.bind(Mode::Normal, KeyCode::Right, KeyModifiers::CONTROL, Command::MoveWordRight);
+        keymap.bind(Mode::Normal, KeyCode::Up, KeyModifiers::SHIFT, Command::MoveDown);
+        keymap.bind(Mode::Normal, KeyCode::Left, KeyModifiers::SHIFT, Command::SwapLeft);
+        keymap.bind(Mode::Normal, KeyCode::Right, KeyModifiers::SHIFT, Command::SwapRight);
+        for mode in [Mode::Normal, Mode::Ident, Mode::StrLit, Mode::NumLit, Mode::CharLit] {
+            keymap.bind(mode.clone(), KeyCode::Char('z'), KeyModifiers::CONTROL, Command::Undo);
+            keymap.bind(mode, KeyCode::Char('y'), KeyModifiers::CONTROL, Command::Redo);
+        }
+        for mode in [Mode::Ident, Mode::StrLit, Mode::NumLit] {
+            keymap.bind(mode, KeyCode::Backspace, none, Command::DeleteChar);
+        }
+        keymap.bind(Mode::Ident, KeyCode::Char(' '), none, Command::EscapeToNormal);
+        keymap.bind(Mode::StrLit, KeyCode::Char('"'), none, Command::EscapeToNormal);
+        keymap.bind(Mode::NumLit, KeyCode::Char('n'), none, Command::EscapeToNormal);
+        keymap
+    }
+}
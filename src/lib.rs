@@ -10,6 +10,7 @@ mod editor;
 mod value;
 mod eval;
 mod shell;
+mod keymap;
 
 use shell::Shell;
 
@@ -31,7 +32,7 @@ pub fn run() {
     term.act(Action::ClearTerminal(Clear::All)).unwrap();
     term.act(Action::EnableRawMode).unwrap();
     term.act(Action::HideCursor).unwrap();
-    let _cleanup = Cleanup {};
+    let cleanup = Cleanup {};
     loop {
         term.batch(Action::ClearTerminal(Clear::All)).unwrap();
         shell.render(&mut term);
@@ -41,7 +42,9 @@ pub fn run() {
         match event {
             Retrieved::Event(Some(Event::Key(ke))) => {
                 if ke.code == KeyCode::Esc {
-                    break;
+                    if shell.on_escape() {
+                        break;
+                    }
                 } else {
                     shell.handle_key_event(ke);
                 }
@@ -50,4 +53,8 @@ pub fn run() {
                 (),
         }
     }
+    drop(cleanup);
+    if !shell.clipboard().is_empty() {
+        println!("{}", shell.clipboard());
+    }
 }
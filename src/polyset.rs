@@ -1,3 +1,5 @@
+use std::cmp::Ordering;
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Polyset<T> {
     elems: Vec<(T, i64)>,
@@ -15,9 +17,89 @@ impl<T> Polyset<T> {
     pub fn keys(&self) -> impl Iterator<Item = &T> {
         self.iter().map(|(x, _)| x)
     }
+
+    /// Number of elements counting multiplicity; `keys().count()` counts distinct elements.
+    pub fn total(&self) -> i64 {
+        self.iter().map(|(_, n)| n).sum()
+    }
 }
 
 impl<T: Ord> Polyset<T> {
+    pub fn overlap(&self, that: &Self) -> i64 {
+        let mut a = self.iter().peekable();
+        let mut b = that.iter().peekable();
+        let mut total = 0;
+        while let (Some((x, m)), Some((y, n))) = (a.peek(), b.peek()) {
+            match x.cmp(y) {
+                Ordering::Less => {
+                    a.next();
+                },
+                Ordering::Greater => {
+                    b.next();
+                },
+                Ordering::Equal => {
+                    total += m.min(n);
+                    a.next();
+                    b.next();
+                },
+            }
+        }
+        total
+    }
+
+    pub fn intersection(self, that: Self) -> Self {
+        let mut a = self.into_iter().peekable();
+        let mut b = that.into_iter().peekable();
+        let mut elems = Vec::new();
+        while let (Some((x, _)), Some((y, _))) = (a.peek(), b.peek()) {
+            match x.cmp(y) {
+                Ordering::Less => {
+                    a.next();
+                },
+                Ordering::Greater => {
+                    b.next();
+                },
+                Ordering::Equal => {
+                    let (x, m) = a.next().unwrap();
+                    let (_, n) = b.next().unwrap();
+                    elems.push((x, m.min(n)));
+                },
+            }
+        }
+        Polyset { elems }
+    }
+
+    pub fn difference(self, that: Self) -> Self {
+        let mut a = self.into_iter().peekable();
+        let mut b = that.into_iter().peekable();
+        let mut elems = Vec::new();
+        while let Some((x, _)) = a.peek() {
+            match b.peek().map(|(y, _)| x.cmp(y)) {
+                Some(Ordering::Greater) => {
+                    b.next();
+                },
+                Some(Ordering::Equal) => {
+                    let (x, m) = a.next().unwrap();
+                    let (_, n) = b.next().unwrap();
+                    if m > n {
+                        elems.push((x, m - n));
+                    }
+                },
+                _ => {
+                    elems.push(a.next().unwrap());
+                },
+            }
+        }
+        Polyset { elems }
+    }
+
+    pub fn get(&self, key: &T) -> i64 {
+        match self.elems.binary_search_by(|(x, _)| x.cmp(key)) {
+            Ok(i) => self.elems[i].1,
+            Err(_) => 0,
+        }
+    }
+
     pub fn from_vec(data: Vec<T>) -> Self {
         data.into_iter().collect()
     }
@@ -113,3 +195,16 @@ impl<T: Ord> FromIterator<T> for Polyset<T> {
         Self::from_iter(iter.into_iter().map(|v| (v, 1)))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn difference_subtracts_multiplicities() {
+        let a = Polyset::from_vec(vec![1, 1, 2, 2, 3]);
+        let b = Polyset::from_vec(vec![1, 2, 2, 4]);
+        let d = a.difference(b);
+        assert_eq!(d.iter().map(|(x, n)| (*x, *n)).collect::<Vec<_>>(), [(1, 1), (3, 1)]);
+    }
+}
@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use std::io::Write;
 use std::rc::Rc;
-use terminal::{Terminal, Action, Color};
+use terminal::{Terminal, Action, Attribute, Color};
 use unicode_width::UnicodeWidthChar;
 
 #[derive(Debug, Clone, Copy)]
@@ -21,6 +21,7 @@ pub struct Symbol {
     pub glyph: char,
     pub foreground: Color,
     pub background: Color,
+    pub attribute: Option<Attribute>,
 }
 
 #[derive(Debug, Clone)]
@@ -62,9 +63,16 @@ enum SizedLayout {
     Text(Rc<[Symbol]>, Size),
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct TextOptions {
+    pub list_limit: usize,
+    pub expand_strings: bool,
+}
+
 #[derive(Debug)]
 pub struct TextBuilder {
     symbols: Vec<Symbol>,
+    options: TextOptions,
 }
 
 pub trait Pretty {
@@ -73,6 +81,18 @@ pub trait Pretty {
 
 pub trait PrettyText {
     fn get_text(&self, text: &mut TextBuilder);
+
+    fn layout_with(&self, options: TextOptions) -> Layout {
+        let mut text = TextBuilder::with_options(options);
+        self.get_text(&mut text);
+        Layout::Text(text.symbols())
+    }
+
+    fn plain_text(&self, options: TextOptions) -> String {
+        let mut text = TextBuilder::with_options(options);
+        self.get_text(&mut text);
+        text.symbols().iter().map(|symbol| symbol.glyph).collect()
+    }
 }
 
 impl Size {
@@ -84,13 +104,31 @@ impl Size {
     }
 }
 
+impl Default for TextOptions {
+    fn default() -> Self {
+        Self {
+            list_limit: 64,
+            expand_strings: false,
+        }
+    }
+}
+
 impl TextBuilder {
     pub fn new() -> Self {
+        Self::with_options(TextOptions::default())
+    }
+
+    pub fn with_options(options: TextOptions) -> Self {
         Self {
             symbols: Vec::new(),
+            options,
         }
     }
 
+    pub fn options(&self) -> TextOptions {
+        self.options
+    }
+
     pub fn symbols(self) -> Vec<Symbol> {
         self.symbols
     }
@@ -100,9 +138,21 @@ impl TextBuilder {
             glyph,
             foreground,
             background,
+            attribute: None,
         });
     }
 
+    pub fn write_str_attr(&mut self, foreground: Color, background: Color, attribute: Attribute, s: &str) {
+        for glyph in s.chars() {
+            self.symbols.push(Symbol {
+                glyph,
+                foreground,
+                background,
+                attribute: Some(attribute),
+            });
+        }
+    }
+
     pub fn write_str(&mut self, foreground: Color, background: Color, s: &str) {
         for glyph in s.chars() {
             self.write_char(foreground, background, glyph);
@@ -121,6 +171,14 @@ impl Layout {
         Layout::Text(text.symbols())
     }
 
+    pub fn hconcat_sep(layouts: Vec<Layout>, separator: Symbol) -> Self {
+        Layout::HConcat(layouts.into_iter().intersperse(Layout::VLine(separator)).collect())
+    }
+
+    pub fn vconcat_sep(layouts: Vec<Layout>, separator: Symbol) -> Self {
+        Layout::VConcat(layouts.into_iter().intersperse(Layout::HLine(separator)).collect())
+    }
+
     fn to_eval(&self) -> EvalLayout {
         match self {
             Self::Empty => {
@@ -163,7 +221,7 @@ impl Layout {
         }
     }
 
-    pub fn display<W: Write>(&self, pos: Pos, mut size: Size, term: &mut Terminal<W>) {
+    fn solve(&self, mut size: Size) -> SizedLayout {
         let mut e = self.to_eval();
         let (mut layout, score) = e.eval(size).unwrap();
         while size.height >= 1 {
@@ -174,7 +232,76 @@ impl Layout {
                 }
             }
         }
-        layout.display(pos, term)
+        layout
+    }
+
+    pub fn display<W: Write>(&self, pos: Pos, size: Size, term: &mut Terminal<W>) {
+        self.solve(size).display(pos, term)
+    }
+
+    pub fn render(&self, size: Size) -> Vec<Vec<char>> {
+        let mut grid = vec![vec![' '; size.width]; size.height];
+        self.solve(size).render(Pos { x: 0, y: 0 }, &mut grid);
+        grid
+    }
+
+    pub fn dump(&self, size: Size) -> String {
+        let mut out = String::new();
+        self.write_dump(0, &mut out);
+        out.push('\n');
+        self.solve(size).write_dump(0, &mut out);
+        out.push('\n');
+        for row in self.render(size) {
+            out.extend(row);
+            out.push('\n');
+        }
+        out
+    }
+
+    fn write_dump(&self, depth: usize, out: &mut String) {
+        out.push_str(&"  ".repeat(depth));
+        match self {
+            Self::Empty => {
+                out.push_str("Empty\n");
+            },
+            Self::HConcat(layouts) => {
+                out.push_str("HConcat\n");
+                for layout in layouts {
+                    layout.write_dump(depth + 1, out);
+                }
+            },
+            Self::VConcat(layouts) => {
+                out.push_str("VConcat\n");
+                for layout in layouts {
+                    layout.write_dump(depth + 1, out);
+                }
+            },
+            Self::HLine(symbol) => {
+                out.push_str(&format!("HLine {:?}\n", symbol.glyph));
+            },
+            Self::VLine(symbol) => {
+                out.push_str(&format!("VLine {:?}\n", symbol.glyph));
+            },
+            Self::Text(symbols) => {
+                out.push_str(&format!("Text {:?}\n", symbols.iter().map(|symbol| symbol.glyph).collect::<String>()));
+            },
+            Self::ExactWidth(layout, width) => {
+                out.push_str(&format!("ExactWidth {width}\n"));
+                layout.write_dump(depth + 1, out);
+            },
+            Self::ExactHeight(layout, height) => {
+                out.push_str(&format!("ExactHeight {height}\n"));
+                layout.write_dump(depth + 1, out);
+            },
+            Self::Weight(layout, factor) => {
+                out.push_str(&format!("Weight {factor}\n"));
+                layout.write_dump(depth + 1, out);
+            },
+            Self::Diminish(layout) => {
+                out.push_str("Diminish\n");
+                layout.write_dump(depth + 1, out);
+            },
+        }
     }
 }
 
@@ -388,30 +515,56 @@ impl SizedLayout {
         }
     }
 
-    fn display<W: Write>(&self, pos: Pos, term: &mut Terminal<W>) {
+    fn write_dump(&self, depth: usize, out: &mut String) {
+        let size = self.size();
+        out.push_str(&"  ".repeat(depth));
+        match self {
+            Self::Empty(_) => {
+                out.push_str(&format!("Empty {}x{}\n", size.width, size.height));
+            },
+            Self::HConcat(layouts) => {
+                out.push_str(&format!("HConcat {}x{}\n", size.width, size.height));
+                for layout in layouts.iter() {
+                    layout.write_dump(depth + 1, out);
+                }
+            },
+            Self::VConcat(layouts) => {
+                out.push_str(&format!("VConcat {}x{}\n", size.width, size.height));
+                for layout in layouts.iter() {
+                    layout.write_dump(depth + 1, out);
+                }
+            },
+            Self::Fill(symbol, _) => {
+                out.push_str(&format!("Fill {:?} {}x{}\n", symbol.glyph, size.width, size.height));
+            },
+            Self::Text(symbols, _) => {
+                let s: String = symbols.iter().map(|symbol| symbol.glyph).collect();
+                out.push_str(&format!("Text {:?} {}x{}\n", s, size.width, size.height));
+            },
+        }
+    }
+
+    fn cells<F: FnMut(Pos, &Symbol)>(&self, pos: Pos, f: &mut F) {
         match self {
             Self::Empty(_) => {},
             Self::HConcat(layouts) => {
                 let mut pos = pos;
                 for layout in layouts.iter() {
-                    layout.display(pos, term);
+                    layout.cells(pos, f);
                     pos.x += layout.size().width;
                 }
             },
             Self::VConcat(layouts) => {
                 let mut pos = pos;
                 for layout in layouts.iter() {
-                    layout.display(pos, term);
+                    layout.cells(pos, f);
                     pos.y += layout.size().height;
                 }
             },
             Self::Fill(symbol, size) => {
-                term.batch(Action::SetForegroundColor(symbol.foreground)).unwrap();
-                term.batch(Action::SetBackgroundColor(symbol.background)).unwrap();
                 for y in pos.y .. pos.y + size.height {
-                    term.batch(Action::MoveCursorTo(pos.x as u16, y as u16)).unwrap();
-                    for _ in 0 .. size.width {
-                        write!(term, "{}", symbol.glyph).unwrap();
+                    for x in pos.x .. pos.x + size.width {
+                        f(Pos { x, y }, symbol);
                     }
                 }
             },
@@ -426,24 +579,42 @@ impl SizedLayout {
                         if cursor.y >= pos.y + size.height {
                             break;
                         }
-                        term.batch(Action::MoveCursorTo(cursor.x as u16, cursor.y as u16)).unwrap();
-                        term.batch(Action::SetForegroundColor(symbol.foreground)).unwrap();
-                        term.batch(Action::SetBackgroundColor(symbol.background)).unwrap();
-                        write!(term, "{}", symbol.glyph).unwrap();
+                        f(cursor, symbol);
                         cursor.x += advance;
                     }
                 }
-                term.batch(Action::ResetColor).unwrap();
             },
         }
     }
+
+    fn render(&self, pos: Pos, grid: &mut [Vec<char>]) {
+        self.cells(pos, &mut |pos, symbol| {
+            if let Some(cell) = grid.get_mut(pos.y).and_then(|row| row.get_mut(pos.x)) {
+                *cell = symbol.glyph;
+            }
+        });
+    }
+
+    fn display<W: Write>(&self, pos: Pos, term: &mut Terminal<W>) {
+        self.cells(pos, &mut |pos, symbol| {
+            term.batch(Action::MoveCursorTo(pos.x as u16, pos.y as u16)).unwrap();
+            term.batch(Action::SetForegroundColor(symbol.foreground)).unwrap();
+            term.batch(Action::SetBackgroundColor(symbol.background)).unwrap();
+            if let Some(attribute) = symbol.attribute {
+                term.batch(Action::SetAttribute(attribute)).unwrap();
+            }
+            write!(term, "{}", symbol.glyph).unwrap();
+            if symbol.attribute.is_some() {
+                term.batch(Action::SetAttribute(Attribute::Reset)).unwrap();
+            }
+        });
+        term.batch(Action::ResetColor).unwrap();
+    }
 }
 
 impl<T: PrettyText> Pretty for T {
     fn layout(&self) -> Layout {
-        let mut text = TextBuilder::new();
-        self.get_text(&mut text);
-        Layout::Text(text.symbols())
+        self.layout_with(TextOptions::default())
     }
 }
 
@@ -458,3 +629,134 @@ impl PrettyText for String {
         (self as &str).get_text(text);
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use std::cell::RefCell;
+    use std::io;
+    use super::*;
+
+    #[derive(Clone, Default)]
+    pub struct MemoryWriter(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for MemoryWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl MemoryWriter {
+        pub fn screen(&self, size: Size) -> Vec<String> {
+            let output = String::from_utf8(self.0.borrow().clone()).unwrap();
+            let mut grid = vec![vec![' '; size.width]; size.height];
+            let mut cursor = Pos { x: 0, y: 0 };
+            let mut chars = output.chars();
+            while let Some(c) = chars.next() {
+                if c == '\x1b' {
+                    assert_eq!(chars.next(), Some('['));
+                    let mut params = String::new();
+                    let command = loop {
+                        match chars.next().unwrap() {
+                            c @ '@' ..= '~' => break c,
+                            c => params.push(c),
+                        }
+                    };
+                    if command == 'H' {
+                        let (row, column) = params.split_once(';').unwrap();
+                        cursor = Pos { x: column.parse::<usize>().unwrap() - 1, y: row.parse::<usize>().unwrap() - 1 };
+                    }
+                } else {
+                    if let Some(cell) = grid.get_mut(cursor.y).and_then(|row| row.get_mut(cursor.x)) {
+                        *cell = c;
+                    }
+                    cursor.x += c.width().unwrap_or(0);
+                }
+            }
+            grid.into_iter().map(|row| row.into_iter().collect()).collect()
+        }
+    }
+
+    pub fn memory_terminal() -> (Terminal<MemoryWriter>, MemoryWriter) {
+        let writer = MemoryWriter::default();
+        (Terminal::custom(writer.clone()), writer)
+    }
+
+    fn text(s: &str) -> Layout {
+        let mut text = TextBuilder::new();
+        text.write_str_default(s);
+        Layout::Text(text.symbols())
+    }
+
+    fn render(layout: &Layout, size: Size) -> Vec<String> {
+        let grid: Vec<String> = layout.render(size).into_iter().map(|row| row.into_iter().collect()).collect();
+        let (mut term, output) = memory_terminal();
+        layout.display(Pos { x: 0, y: 0 }, size, &mut term);
+        term.flush_batch().unwrap();
+        assert_eq!(output.screen(size), grid);
+        grid
+    }
+
+    #[test]
+    fn render_text() {
+        let size = Size { width: 4, height: 3 };
+        assert_eq!(render(&text("abcdef"), size), ["abcd", "ef  ", "    "]);
+    }
+
+    #[test]
+    fn render_hconcat() {
+        let layout = Layout::HConcat(vec![text("ab"), text("cd")]);
+        assert_eq!(render(&layout, Size { width: 6, height: 2 }), ["ab  cd", "      "]);
+    }
+
+    #[test]
+    fn render_vconcat() {
+        let layout = Layout::VConcat(vec![text("ab"), text("cd")]);
+        assert_eq!(render(&layout, Size { width: 3, height: 3 }), ["ab ", "cd ", "   "]);
+    }
+
+    #[test]
+    fn hconcat_sep_separates_children() {
+        let bar = Symbol { glyph: '|', foreground: Color::Grey, background: Color::Black, attribute: None };
+        let layout = Layout::hconcat_sep(vec![text("a"), text("b"), text("c")], bar);
+        let Layout::HConcat(children) = &layout else { panic!() };
+        assert!(matches!(children[..], [Layout::Text(_), Layout::VLine(_), Layout::Text(_), Layout::VLine(_), Layout::Text(_)]));
+        assert_eq!(render(&layout, Size { width: 5, height: 1 }), ["a|b|c"]);
+    }
+
+    #[test]
+    fn vconcat_sep_separates_children() {
+        let bar = Symbol { glyph: '-', foreground: Color::Grey, background: Color::Black, attribute: None };
+        let layout = Layout::vconcat_sep(vec![text("a"), text("b")], bar);
+        let Layout::VConcat(children) = &layout else { panic!() };
+        assert!(matches!(children[..], [Layout::Text(_), Layout::HLine(_), Layout::Text(_)]));
+        assert_eq!(render(&layout, Size { width: 1, height: 3 }), ["a", "-", "b"]);
+    }
+
+    #[test]
+    fn dump_shows_structure() {
+        let dump = Layout::HConcat(vec![text("ab"), text("cd")]).dump(Size { width: 4, height: 1 });
+        assert!(dump.contains("HConcat\n  Text \"ab\"\n  Text \"cd\"\n"));
+        assert!(dump.contains("HConcat 4x1\n  Text \"ab\" 2x1\n  Text \"cd\" 2x1\n"));
+        assert!(dump.ends_with("abcd\n"));
+    }
+
+    #[test]
+    fn bold_symbols_set_attribute() {
+        let mut text = TextBuilder::new();
+        text.write_str_attr(Color::Red, Color::Black, Attribute::Bold, "a");
+        let (mut term, output) = memory_terminal();
+        Layout::Text(text.symbols()).display(Pos { x: 0, y: 0 }, Size { width: 1, height: 1 }, &mut term);
+        term.flush_batch().unwrap();
+        let bytes = output.0.borrow().clone();
+        let output = String::from_utf8(bytes).unwrap();
+        let bold = output.find("\x1b[1m").unwrap();
+        let glyph = output.find('a').unwrap();
+        let reset = output.find("\x1b[0m").unwrap();
+        assert!(bold < glyph && glyph < reset);
+    }
+}
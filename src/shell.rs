@@ -1,112 +1,358 @@
 use std::collections::HashMap;
 use std::io::Write;
-use terminal::{Terminal, KeyEvent, KeyCode, Value, Retrieved, Color};
+use std::mem;
+use unicode_width::UnicodeWidthStr;
+use terminal::{Terminal, KeyEvent, KeyCode, Value, Retrieved, Color, Attribute};
 use crate::{
-    editor::{Cursor, Mode},
-    pretty::{Pretty, Pos, Size, Layout, Symbol},
-    eval::VM,
+    editor::{Cursor, CursorShape, Mode},
+    syntax::{self, Expr},
+    pretty::{Pretty, Pos, Size, Layout, Symbol, TextBuilder, TextOptions},
+    eval::{VM, Trace, PRIMITIVES, ITERATION_LIMIT},
+    keymap::{KeyMap, Command, fuzzy_find},
 };
 
+const UNDO_LIMIT: usize = 256;
+
 #[derive(Debug, Clone)]
 pub struct Shell {
     cursor: Cursor,
+    registers: Vec<Cursor>,
+    register: usize,
+    undo: Vec<Cursor>,
+    redo: Vec<Cursor>,
+    keymap: KeyMap,
+    palette: Option<String>,
+    clipboard: String,
+    text_options: TextOptions,
+    answers: Vec<String>,
+    answer: Option<String>,
+    status: Option<String>,
+    max_iterations: usize,
+    size: Size,
+    show_diff: bool,
+    step_into: bool,
 }
 
 impl Shell {
     pub fn new() -> Self {
+        Self::with_keymap(KeyMap::default())
+    }
+
+    pub fn with_keymap(keymap: KeyMap) -> Self {
         Self {
             cursor: Cursor::empty(),
+            registers: vec![Cursor::empty(); 9],
+            register: 0,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            keymap,
+            palette: None,
+            clipboard: String::new(),
+            text_options: TextOptions::default(),
+            answers: Vec::new(),
+            answer: None,
+            status: None,
+            max_iterations: ITERATION_LIMIT,
+            size: Size::null(),
+            show_diff: false,
+            step_into: false,
         }
     }
 
+    pub fn clipboard(&self) -> &str {
+        &self.clipboard
+    }
+
     pub fn handle_key_event(&mut self, event: KeyEvent) {
-        match self.cursor.mode() {
-            Mode::Normal => self.handle_key_event_normal(event),
-            Mode::Ident => self.handle_key_event_ident(event),
-            Mode::StrLit => self.handle_key_event_strlit(event),
-            Mode::NumLit => self.handle_key_event_numlit(event),
-        }
-    }
-
-    pub fn handle_key_event_normal(&mut self, event: KeyEvent) {
-        if event.modifiers.is_empty() {
-            match event.code {
-                KeyCode::Left =>
-                    self.cursor.move_left(),
-                KeyCode::Right =>
-                    self.cursor.move_right(),
-                KeyCode::Up =>
-                    self.cursor.move_up(),
-                KeyCode::Backspace =>
-                    self.cursor.delete_before(),
-                KeyCode::Char('i') =>
-                    self.cursor.insert(Cursor::empty_ident()),
-                KeyCode::Char('n') =>
-                    self.cursor.insert(Cursor::empty_num_lit()),
-                KeyCode::Char('"') =>
-                    self.cursor.insert(Cursor::empty_str_lit()),
-                KeyCode::Char('{') =>
-                    self.cursor.insert(Cursor::empty_quote()),
-                KeyCode::Char('}') | KeyCode::Down =>
-                    self.cursor.move_out(),
-                _ =>
-                    (),
+        self.status = None;
+        if self.palette.is_some() {
+            self.handle_key_event_palette(event);
+            return;
+        }
+        if self.answer.is_some() {
+            self.handle_key_event_answer(event);
+            return;
+        }
+        let mode = self.cursor.mode();
+        if let Some(command) = self.keymap.lookup(&mode, &event) {
+            self.run_command(command);
+        } else if mode != Mode::Normal && event.modifiers.is_empty() {
+            if let KeyCode::Char(c) = event.code {
+                let before = self.cursor.clone();
+                self.cursor.input(c);
+                self.record(before);
             }
         }
     }
 
-    pub fn handle_key_event_ident(&mut self, event: KeyEvent) {
-        if event.modifiers.is_empty() {
-            match event.code {
-                KeyCode::Char(c) =>
-                    if c.is_whitespace() {
-                        self.cursor.escape_to_normal();
-                    } else {
-                        self.cursor.input(c);
-                    },
-                _ =>
-                    (),
-            }
+    pub fn on_escape(&mut self) -> bool {
+        if self.palette.take().is_some() || self.answer.take().is_some() {
+            false
+        } else if self.cursor.mode() != Mode::Normal {
+            self.run_command(Command::EscapeToNormal);
+            false
+        } else {
+            true
         }
     }
 
-    pub fn handle_key_event_strlit(&mut self, event: KeyEvent) {
-        if event.modifiers.is_empty() {
-            match event.code {
-                KeyCode::Char(c) =>
-                    if c == '"' {
-                        self.cursor.escape_to_normal();
-                    } else {
-                        self.cursor.input(c);
+    pub fn handle_key_event_palette(&mut self, event: KeyEvent) {
+        let Some(query) = &mut self.palette else {
+            return;
+        };
+        match event.code {
+            KeyCode::Char(c) =>
+                query.push(c),
+            KeyCode::Backspace =>
+                if query.pop().is_none() {
+                    self.palette = None;
+                },
+            KeyCode::Enter => {
+                let query = self.palette.take().unwrap_or_default();
+                if query.is_empty() {
+                    return;
+                }
+                let Some(&name) = Self::palette_matches(&query).first() else {
+                    return;
+                };
+                match Command::from_name(name) {
+                    Some(command) => self.run_command(command),
+                    None => {
+                        let before = self.cursor.clone();
+                        self.cursor.insert(Cursor::Edge(vec![Expr::Ident(name.to_string())], Vec::new()));
+                        self.record(before);
                     },
-                _ =>
-                    (),
+                }
+            },
+            _ =>
+                (),
+        }
+    }
+
+    pub fn handle_key_event_answer(&mut self, event: KeyEvent) {
+        let Some(answer) = &mut self.answer else {
+            return;
+        };
+        match event.code {
+            KeyCode::Char(c) =>
+                answer.push(c),
+            KeyCode::Backspace =>
+                if answer.pop().is_none() {
+                    self.answer = None;
+                },
+            KeyCode::Enter =>
+                self.answers.extend(self.answer.take()),
+            _ =>
+                (),
+        }
+    }
+
+    fn palette_matches(query: &str) -> Vec<&'static str> {
+        fuzzy_find(query, Command::ALL.iter().map(Command::name).chain(PRIMITIVES.iter().copied()))
+    }
+
+    // Literals are recorded as a single edit: the snapshot is taken when the literal is opened
+    // from normal mode, so keystrokes inside it don't add undo steps of their own.
+    fn record(&mut self, before: Cursor) {
+        if before.mode() == Mode::Normal && self.cursor != before {
+            self.undo.push(before);
+            if self.undo.len() > UNDO_LIMIT {
+                self.undo.remove(0);
             }
+            self.redo.clear();
         }
     }
 
-    pub fn handle_key_event_numlit(&mut self, event: KeyEvent) {
-        if event.modifiers.is_empty() {
-            match event.code {
-                KeyCode::Char(c) =>
-                    if c == 'n' {
-                        self.cursor.escape_to_normal();
-                    } else {
-                        self.cursor.input(c);
-                    },
-                _ =>
-                    (),
+    fn write_file(&mut self, path: &str, contents: String) {
+        if let Err(err) = std::fs::write(path, contents) {
+            self.status = Some(format!("cannot write {path}: {err}"));
+        }
+    }
+
+    pub fn run_command(&mut self, command: Command) {
+        let before = self.cursor.clone();
+        match command {
+            Command::MoveLeft =>
+                self.cursor.move_left(),
+            Command::MoveRight =>
+                self.cursor.move_right(),
+            Command::MoveWordLeft =>
+                self.cursor.move_word_left(),
+            Command::MoveWordRight =>
+                self.cursor.move_word_right(),
+            Command::SwapLeft =>
+                self.cursor.swap_left(),
+            Command::SwapRight =>
+                self.cursor.swap_right(),
+            Command::MoveVeryLeft =>
+                self.cursor.move_very_left(),
+            Command::MoveVeryRight =>
+                self.cursor.move_very_right(),
+            Command::MoveUp =>
+                self.cursor.move_up(),
+            Command::MoveDown =>
+                self.cursor.move_down(),
+            Command::MoveOut =>
+                self.cursor.move_out(),
+            Command::JumpMatching =>
+                self.cursor.jump_matching(),
+            Command::DeleteBefore =>
+                self.cursor.delete_before(),
+            Command::DeleteAfter =>
+                self.cursor.delete_after(),
+            Command::DeleteChar =>
+                self.cursor.delete_char(),
+            Command::InsertIdent =>
+                self.cursor.insert(Cursor::empty_ident()),
+            Command::InsertNumLit =>
+                self.cursor.insert(Cursor::empty_num_lit()),
+            Command::InsertStrLit =>
+                self.cursor.insert(Cursor::empty_str_lit()),
+            Command::InsertCharLit =>
+                self.cursor.insert(Cursor::empty_char_lit()),
+            Command::InsertQuote =>
+                self.cursor.insert(Cursor::empty_quote()),
+            Command::WrapInQuote =>
+                self.cursor.wrap_in_quote(),
+            Command::WrapAllInQuote =>
+                self.cursor.wrap_all_in_quote(),
+            Command::UnwrapQuote =>
+                self.cursor.unwrap_quote(),
+            Command::EscapeToNormal => {
+                self.cursor.escape_to_normal();
+                if self.undo.last() == Some(&self.cursor) {
+                    self.undo.pop();
+                }
+            },
+            Command::OpenPalette =>
+                self.palette = Some(String::new()),
+            Command::CopyProgram =>
+                self.clipboard = syntax::to_source(&self.cursor.program()),
+            Command::YankExpr =>
+                if let Some(expr) = self.cursor.prev_expr() {
+                    self.clipboard = syntax::to_source(&vec![expr.clone()]);
+                },
+            Command::PasteProgram =>
+                if let Some(program) = syntax::parse(&self.clipboard) {
+                    self.cursor.insert(Cursor::Edge(program, Vec::new()));
+                },
+            Command::PasteAsQuote =>
+                if let Some(program) = syntax::parse(&self.clipboard) {
+                    self.cursor.insert(Cursor::Edge(vec![Expr::Quote(program)], Vec::new()));
+                },
+            Command::AnswerPrompt =>
+                if self.evaluate().0.pending_prompt().is_some() {
+                    self.answer = Some(String::new());
+                },
+            Command::ResetAnswers =>
+                self.answers.clear(),
+            Command::DumpLayout => {
+                self.write_file("elv-layout.txt", self.layout().dump(self.size));
+            },
+            Command::ToggleStrings =>
+                self.text_options.expand_strings = !self.text_options.expand_strings,
+            Command::LongerLists =>
+                self.text_options.list_limit = self.text_options.list_limit.saturating_mul(2),
+            Command::ShorterLists =>
+                self.text_options.list_limit = (self.text_options.list_limit / 2).max(2),
+            Command::ExportTrace => {
+                self.write_file("elv-trace.txt", self.trace_text());
+            },
+            Command::ToggleDiff =>
+                self.show_diff = !self.show_diff,
+            Command::ToggleStepInto =>
+                self.step_into = !self.step_into,
+            Command::SwitchRegister(n) =>
+                if n != self.register {
+                    self.registers[self.register] = mem::replace(&mut self.cursor, mem::take(&mut self.registers[n]));
+                    self.register = n;
+                    self.undo.clear();
+                    self.redo.clear();
+                },
+            Command::MoreIterations =>
+                self.max_iterations = self.max_iterations.saturating_mul(10),
+            Command::FewerIterations =>
+                self.max_iterations = (self.max_iterations / 10).max(1),
+            Command::Undo =>
+                if let Some(cursor) = self.undo.pop() {
+                    self.redo.push(mem::replace(&mut self.cursor, cursor));
+                },
+            Command::Redo =>
+                if let Some(cursor) = self.redo.pop() {
+                    self.undo.push(mem::replace(&mut self.cursor, cursor));
+                },
+        }
+        if command.is_edit() {
+            self.record(before);
+        }
+    }
+
+    fn evaluate(&self) -> (VM, Trace) {
+        let mut vm = VM::with_input(self.answers.clone()).with_max_iterations(self.max_iterations);
+        let mut trace = HashMap::new();
+        vm.eval_cursor(&mut trace, Cursor::initial(self.cursor.program()));
+        (vm, trace)
+    }
+
+    fn trace_text(&self) -> String {
+        let (_, trace) = self.evaluate();
+        let snapshots = trace.get(&self.cursor.shape()).map_or(&[][..], |snapshots| &snapshots[..]);
+        let texts: Vec<_> = snapshots.iter().map(|snapshot| snapshot.plain_text(self.text_options)).collect();
+        texts.join("~\n")
+    }
+
+    fn is_end(shape: &CursorShape) -> bool {
+        match shape {
+            CursorShape::Edge(_, 0) => true,
+            CursorShape::Quote(_, shape, _) => Self::is_end(shape),
+            _ => false,
+        }
+    }
+
+    fn snapshots<'a>(&self, trace: &'a Trace) -> Vec<&'a VM> {
+        let shape = self.cursor.shape();
+        if self.step_into {
+            let mut keys: Vec<_> = trace.keys().filter(|key| Self::is_end(key)).collect();
+            keys.sort();
+            keys.into_iter()
+                .flat_map(|key| &trace[key])
+                .filter(|snapshot| snapshot.origin() == Some(&shape))
+                .collect()
+        } else {
+            trace.get(&shape).map_or(Vec::new(), |snapshots| snapshots.iter().collect())
+        }
+    }
+
+    fn errors(&self, vm: &VM, trace: &Trace) -> Vec<(usize, String)> {
+        let len = self.cursor.program().len();
+        let snapshot = |i: usize| trace.get(&CursorShape::Edge(i, len - i)).and_then(|snapshots| snapshots.first());
+        let mut errors = Vec::new();
+        for (k, value) in vm.stack().iter().enumerate() {
+            if !value.is_poison() {
+                continue;
+            }
+            let mut first = len;
+            while first > 0 && snapshot(first - 1).is_some_and(|before| before.stack().get(k) == Some(value)) {
+                first -= 1;
             }
+            let reason = value.poison_reason().unwrap_or("poison").to_string();
+            errors.push((first.saturating_sub(1), reason));
         }
+        errors
     }
 
-    pub fn render<W: Write>(&self, term: &mut Terminal<W>) {
+    pub fn render<W: Write>(&mut self, term: &mut Terminal<W>) {
         let size = match term.get(Value::TerminalSize) {
             Ok(Retrieved::TerminalSize(width, height)) =>
                 Size { width: width as usize, height: height as usize },
             _ =>
                 panic!(),
         };
+        self.render_sized(size, term);
+    }
+
+    pub fn render_sized<W: Write>(&mut self, size: Size, term: &mut Terminal<W>) {
+        self.size = size;
         self.layout().display(Pos { x: 0, y: 0 }, size, term);
     }
 }
@@ -118,24 +364,263 @@ impl Pretty for Shell {
             glyph: '≡',
             foreground: Color::Grey,
             background: Color::Black,
+            attribute: None,
         });
-        let debugger = if self.cursor.mode() == Mode::Normal {
-            let mut vm = VM::new();
-            let mut trace = HashMap::new();
-            vm.eval_cursor(&mut trace, Cursor::initial(self.cursor.program()));
-            if let Some(snapshots) = trace.get(&self.cursor.shape()) {
-                let sep = Layout::HLine(Symbol {
+        let (debugger, prompt, errors) = if self.cursor.mode() == Mode::Normal {
+            let (vm, trace) = self.evaluate();
+            let snapshots = self.snapshots(&trace);
+            let debugger = if !snapshots.is_empty() {
+                let sep = Symbol {
                     glyph: '~',
                     foreground: Color::Grey,
                     background: Color::Black,
-                });
-                Layout::VConcat(snapshots.iter().take(16).map(|snapshot| snapshot.layout()).intersperse(sep).collect())
+                    attribute: None,
+                };
+                let mut prev_cursor = self.cursor.clone();
+                prev_cursor.move_left();
+                let befores = match trace.get(&prev_cursor.shape()) {
+                    Some(befores) if self.show_diff && !self.step_into && prev_cursor != self.cursor => &befores[..],
+                    _ => &[],
+                };
+                Layout::vconcat_sep(snapshots.iter().enumerate().take(16).map(|(i, snapshot)| {
+                    match befores.get(i) {
+                        Some(before) => snapshot.diff_layout(before, self.text_options),
+                        None => snapshot.layout_with(self.text_options),
+                    }
+                }).collect(), sep)
             } else {
                 Layout::Empty
-            }
+            };
+            let prompt = match vm.pending_prompt() {
+                Some(prompt) => {
+                    let mut text = TextBuilder::new();
+                    text.write_str(Color::Yellow, Color::Black, &format!("{prompt} "));
+                    match &self.answer {
+                        Some(answer) => text.write_str_default(answer),
+                        None => text.write_str(Color::Grey, Color::Black, "(! to answer)"),
+                    }
+                    Layout::ExactHeight(Box::new(Layout::Text(text.symbols())), 1)
+                },
+                None => {
+                    Layout::Empty
+                },
+            };
+            let errors = Layout::VConcat(self.errors(&vm, &trace).into_iter().map(|(index, reason)| {
+                let mut text = TextBuilder::new();
+                text.write_str_attr(Color::Red, Color::Black, Attribute::Bold, &format!("☠ #{index} "));
+                text.write_str_default(&reason);
+                Layout::ExactHeight(Box::new(Layout::Text(text.symbols())), 1)
+            }).collect());
+            (debugger, prompt, errors)
         } else {
-            Layout::Empty
+            (Layout::Empty, Layout::Empty, Layout::Empty)
         };
-        Layout::VConcat(vec![cmdline, sep, debugger])
+        let palette = match &self.palette {
+            Some(query) => {
+                let mut prompt = TextBuilder::new();
+                prompt.write_str(Color::Yellow, Color::Black, ":");
+                prompt.write_str_default(query);
+                let width = 1 + query.width();
+                let mut matches = TextBuilder::new();
+                for name in Self::palette_matches(query) {
+                    matches.write_str(Color::Grey, Color::Black, &format!("{name} "));
+                }
+                let sep = Symbol {
+                    glyph: '│',
+                    foreground: Color::Grey,
+                    background: Color::Black,
+                    attribute: None,
+                };
+                let row = Layout::hconcat_sep(vec![
+                    Layout::ExactWidth(Box::new(Layout::Text(prompt.symbols())), width),
+                    Layout::Text(matches.symbols()),
+                ], sep);
+                Layout::ExactHeight(Box::new(row), 1)
+            },
+            None => {
+                Layout::Empty
+            },
+        };
+        let status = match &self.status {
+            Some(message) => {
+                let mut text = TextBuilder::new();
+                text.write_str_attr(Color::Red, Color::Black, Attribute::Bold, message);
+                Layout::ExactHeight(Box::new(Layout::Text(text.symbols())), 1)
+            },
+            None => {
+                Layout::Empty
+            },
+        };
+        Layout::VConcat(vec![cmdline, palette, status, prompt, errors, sep, debugger])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use terminal::KeyModifiers;
+    use crate::pretty::tests::memory_terminal;
+
+    fn press(shell: &mut Shell, keys: &str) {
+        for c in keys.chars() {
+            shell.handle_key_event(KeyEvent::from(KeyCode::Char(c)));
+        }
+    }
+
+    fn source(shell: &Shell) -> String {
+        let mut cursor = shell.cursor.clone();
+        cursor.escape_to_normal();
+        syntax::to_source(&cursor.program())
+    }
+
+    #[test]
+    fn undo_skips_cursor_movement() {
+        let mut shell = Shell::new();
+        press(&mut shell, "idup n5n");
+        assert_eq!(source(&shell), "dup 5");
+        for code in [KeyCode::Left, KeyCode::Left, KeyCode::Right] {
+            shell.handle_key_event(KeyEvent::from(code));
+        }
+        shell.handle_key_event(KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL));
+        assert_eq!(shell.cursor, Cursor::Edge(syntax::parse("dup").unwrap(), Vec::new()));
+        shell.handle_key_event(KeyEvent::new(KeyCode::Char('y'), KeyModifiers::CONTROL));
+        assert_eq!(shell.cursor, Cursor::Edge(syntax::parse("dup").unwrap(), syntax::parse("5").unwrap()));
+    }
+
+    #[test]
+    fn undo_reverts_whole_literal() {
+        let mut shell = Shell::new();
+        press(&mut shell, "n12n");
+        shell.handle_key_event(KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL));
+        assert_eq!(shell.cursor, Cursor::Edge(Vec::new(), Vec::new()));
+        assert!(shell.undo.is_empty());
+    }
+
+    #[test]
+    fn empty_literal_leaves_no_undo_step() {
+        let mut shell = Shell::new();
+        press(&mut shell, "n1nnn");
+        assert_eq!(shell.undo.len(), 1);
+    }
+
+    #[test]
+    fn render_writes_glyph_cells() {
+        let mut shell = Shell::new();
+        press(&mut shell, "n1nn2n");
+        let (mut term, output) = memory_terminal();
+        let size = Size { width: 12, height: 5 };
+        shell.render_sized(size, &mut term);
+        term.flush_batch().unwrap();
+        assert_eq!(output.screen(size), [
+            "1 2         ",
+            "≡≡≡≡≡≡≡≡≡≡≡≡",
+            "1   1       ",
+            "0   2       ",
+            "            ",
+        ]);
+    }
+
+    #[test]
+    fn list_limit_is_adjustable() {
+        let mut shell = Shell::new();
+        let limit = shell.text_options.list_limit;
+        press(&mut shell, "<");
+        assert_eq!(shell.text_options.list_limit, limit / 2);
+        press(&mut shell, ">>");
+        assert_eq!(shell.text_options.list_limit, limit * 2);
+    }
+
+    #[test]
+    fn remapped_key_runs_command() {
+        let mut keymap = KeyMap::new();
+        keymap.bind(Mode::Normal, KeyCode::Char('x'), KeyModifiers::empty(), Command::InsertQuote);
+        let mut shell = Shell::with_keymap(keymap);
+        press(&mut shell, "ix");
+        assert_eq!(source(&shell), "{}");
+    }
+
+    #[test]
+    fn palette_runs_named_command() {
+        let mut shell = Shell::new();
+        press(&mut shell, "n1n:wrap-in-quote");
+        shell.handle_key_event(KeyEvent::from(KeyCode::Enter));
+        assert!(shell.palette.is_none());
+        assert_eq!(source(&shell), "{1}");
+    }
+
+    #[test]
+    fn palette_row_separates_query_from_matches() {
+        let mut shell = Shell::new();
+        press(&mut shell, ":wrap-all");
+        let screen = shell.layout().render(Size { width: 30, height: 10 });
+        let rows: Vec<String> = screen.iter().map(|row| row.iter().collect()).collect();
+        assert_eq!(rows[1], ":wrap-all│wrap-all-in-quote   ");
+    }
+
+    #[test]
+    fn palette_without_match_does_nothing() {
+        let mut shell = Shell::new();
+        press(&mut shell, "n1n:zzzzzz");
+        shell.handle_key_event(KeyEvent::from(KeyCode::Enter));
+        assert!(shell.palette.is_none());
+        assert_eq!(source(&shell), "1");
+    }
+
+    #[test]
+    fn trace_text_shows_stack() {
+        let mut shell = Shell::new();
+        press(&mut shell, "n1nn2ni+ ");
+        assert_eq!(shell.trace_text(), "0   3\n");
+        shell.run_command(Command::MoveLeft);
+        assert_eq!(shell.trace_text(), "1   1\n0   2\n");
+    }
+
+    #[test]
+    fn status_shows_until_next_key() {
+        let mut shell = Shell::new();
+        shell.write_file("no-such-dir/elv-layout.txt", String::new());
+        let status = shell.status.clone().unwrap();
+        assert!(status.starts_with("cannot write no-such-dir/elv-layout.txt: "), "{status}");
+        let screen = shell.layout().render(Size { width: 60, height: 4 });
+        assert!(screen.iter().any(|row| row.iter().collect::<String>().starts_with("cannot write")));
+        press(&mut shell, "n1n");
+        assert!(shell.status.is_none());
+    }
+
+    #[test]
+    fn errors_lists_each_poison() {
+        let mut shell = Shell::new();
+        shell.cursor = Cursor::Edge(syntax::parse("1 0 / 2 \"x\" num").unwrap(), Vec::new());
+        let (vm, trace) = shell.evaluate();
+        assert_eq!(shell.errors(&vm, &trace), [(2, "division by zero".to_string()), (5, "not a number".to_string())]);
+    }
+
+    #[test]
+    fn step_into_shows_child_stacks() {
+        let mut shell = Shell::new();
+        shell.cursor = Cursor::Edge(syntax::parse("{1 2} collect {inc} map").unwrap(), Vec::new());
+        shell.step_into = true;
+        let (_, trace) = shell.evaluate();
+        let stacks: Vec<_> = shell.snapshots(&trace).into_iter().map(|vm| vm.stack().to_vec()).collect();
+        assert_eq!(stacks, [[crate::value::Value::new_i64(2)], [crate::value::Value::new_i64(3)]]);
+    }
+
+    #[test]
+    fn registers_keep_separate_programs() {
+        let mut shell = Shell::new();
+        press(&mut shell, "n1n2n2n");
+        assert_eq!(source(&shell), "2");
+        press(&mut shell, "1");
+        assert_eq!(source(&shell), "1");
+        press(&mut shell, "2");
+        assert_eq!(source(&shell), "2");
+    }
+
+    #[test]
+    fn paste_as_quote_inserts_one_quote() {
+        let mut shell = Shell::new();
+        shell.clipboard = "1 2".to_string();
+        press(&mut shell, "Q");
+        assert_eq!(shell.cursor.program(), syntax::parse("{1 2}").unwrap());
     }
 }
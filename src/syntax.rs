@@ -1,12 +1,19 @@
+use std::iter::Peekable;
+use std::str::Chars;
 use num_bigint::BigInt;
 use terminal::Color;
-use crate::pretty::{PrettyText, TextBuilder};
+use crate::{
+    value::Float,
+    pretty::{PrettyText, TextBuilder},
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Expr {
     Ident(String),
     StrLit(String),
+    CharLit(char),
     NumLit(BigInt),
+    FloatLit(Float),
     Quote(Program),
 }
 
@@ -29,9 +36,15 @@ impl PrettyText for Expr {
                     text.write_str(Color::Green, Color::Black, &s);
                 }
             },
+            Expr::CharLit(c) => {
+                text.write_str(Color::Green, Color::Black, &format!("'{c}"));
+            },
             Expr::NumLit(n) => {
                 text.write_str(Color::Green, Color::Black, &format!("{n}"));
             },
+            Expr::FloatLit(Float(f)) => {
+                text.write_str(Color::Green, Color::Black, &format!("{f:?}"));
+            },
             Expr::Quote(program) => {
                 text.write_str_default("{");
                 program.get_text(text);
@@ -51,3 +64,114 @@ impl PrettyText for Program {
         }
     }
 }
+
+pub fn to_source(program: &Program) -> String {
+    let mut source = String::new();
+    write_source(program, &mut source);
+    source
+}
+
+fn write_source(program: &Program, source: &mut String) {
+    for (i, expr) in program.iter().enumerate() {
+        if i > 0 {
+            source.push(' ');
+        }
+        match expr {
+            Expr::Ident(s) => {
+                source.push_str(s);
+            },
+            Expr::StrLit(s) => {
+                source.push('"');
+                for c in s.chars() {
+                    if c == '"' || c == '\\' {
+                        source.push('\\');
+                    }
+                    source.push(c);
+                }
+                source.push('"');
+            },
+            Expr::CharLit(c) => {
+                source.push('\'');
+                source.push(*c);
+            },
+            Expr::NumLit(n) => {
+                source.push_str(&format!("{n}"));
+            },
+            Expr::FloatLit(Float(f)) => {
+                source.push_str(&format!("{f:?}"));
+            },
+            Expr::Quote(program) => {
+                source.push('{');
+                write_source(program, source);
+                source.push('}');
+            },
+        }
+    }
+}
+
+pub fn parse(source: &str) -> Option<Program> {
+    let mut chars = source.chars().peekable();
+    let program = parse_program(&mut chars)?;
+    match chars.next() {
+        None => Some(program),
+        Some(_) => None,
+    }
+}
+
+fn parse_program(chars: &mut Peekable<Chars>) -> Option<Program> {
+    let mut program = Vec::new();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '}' {
+            break;
+        } else if c == '{' {
+            chars.next();
+            program.push(Expr::Quote(parse_program(chars)?));
+            if chars.next() != Some('}') {
+                return None;
+            }
+        } else if c == '"' {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => s.push(chars.next()?),
+                    c => s.push(c),
+                }
+            }
+            program.push(Expr::StrLit(s));
+        } else if c == '\'' {
+            chars.next();
+            program.push(Expr::CharLit(chars.next()?));
+        } else {
+            let mut s = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '{' || c == '}' || c == '"' {
+                    break;
+                }
+                s.push(c);
+                chars.next();
+            }
+            match (s.parse(), s.parse()) {
+                (Ok(n), _) => program.push(Expr::NumLit(n)),
+                (_, Ok(f)) if s.contains('.') => program.push(Expr::FloatLit(Float(f))),
+                _ => program.push(Expr::Ident(s)),
+            }
+        }
+    }
+    Some(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_round_trips() {
+        let source = r#"1 -2 2.5 "a \"b\" \\c" 'x {dup {"}"}} foo"#;
+        let program = parse(source).unwrap();
+        assert_eq!(parse(&to_source(&program)), Some(program));
+    }
+}
@@ -1,7 +1,8 @@
 use std::sync::Arc;
+use std::cmp::Ordering;
 use std::iter::{zip, once};
 use num_bigint::BigInt;
-use num_traits::cast::ToPrimitive;
+use num_traits::cast::{FromPrimitive, ToPrimitive};
 use terminal::Color;
 use crate::{
     polyset::Polyset,
@@ -13,8 +14,10 @@ use crate::{
 pub enum Shape {
     Void,
     Any,
+    Bool,
     Char,
     Num,
+    Float,
     Tuple(Vec<Shape>),
     Array(Box<Shape>, usize),
     List(Box<Shape>),
@@ -30,14 +33,19 @@ pub enum Val {
     Quote(Cursor),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone)]
 pub enum Value {
-    Poison,
+    Poison(Option<String>),
+    Bool(bool),
     Char(char),
     Num(i64),
+    Float(Float),
     Ptr(Arc<Val>),
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct Float(pub f64);
+
 impl Shape {
     pub fn union(self, that: Shape) -> Shape {
         match (self, that) {
@@ -45,8 +53,10 @@ impl Shape {
             (shape1, Self::Void) => shape1,
             (Self::Any, _) => Self::Any,
             (_, Self::Any) => Self::Any,
+            (Self::Bool, Self::Bool) => Self::Bool,
             (Self::Char, Self::Char) => Self::Char,
             (Self::Num, Self::Num) => Self::Num,
+            (Self::Float, Self::Float) => Self::Float,
             (Self::Tuple(shapes1), Self::Tuple(shapes2)) => {
                 if shapes1.len() == shapes2.len() {
                     Self::Tuple(zip(shapes1.into_iter(), shapes2.into_iter()).map(|(s1, s2)| s1.union(s2)).collect())
@@ -116,8 +126,10 @@ impl Shape {
             shape if shape.is_string() => Value::new_str("string"),
             Self::Void => Value::new_str("void"),
             Self::Any => Value::new_str("any"),
+            Self::Bool => Value::new_str("bool"),
             Self::Char => Value::new_str("char"),
             Self::Num => Value::new_str("num"),
+            Self::Float => Value::new_str("float"),
             Self::Tuple(shapes) => {
                 let reprs: Vec<_> = once(Value::new_str("tuple")).chain(shapes.iter().map(Self::repr)).collect();
                 Value::new_list(reprs)
@@ -148,6 +160,72 @@ impl Shape {
     }
 }
 
+impl PartialEq for Float {
+    fn eq(&self, that: &Self) -> bool {
+        self.cmp(that) == Ordering::Equal
+    }
+}
+
+impl Eq for Float {}
+
+impl PartialOrd for Float {
+    fn partial_cmp(&self, that: &Self) -> Option<Ordering> {
+        Some(self.cmp(that))
+    }
+}
+
+impl Ord for Float {
+    fn cmp(&self, that: &Self) -> Ordering {
+        self.0.total_cmp(&that.0)
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, that: &Self) -> bool {
+        self.cmp(that) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, that: &Self) -> Option<Ordering> {
+        Some(self.cmp(that))
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, that: &Self) -> Ordering {
+        match (self.rank(), that.rank()) {
+            (2, 2) => match (self, that) {
+                (Self::Num(a), Self::Num(b)) => a.cmp(b),
+                (Self::Float(Float(a)), Self::Float(Float(b))) if a == b => Ordering::Equal,
+                (Self::Float(a), Self::Float(b)) => a.cmp(b),
+                (Self::Float(Float(a)), _) => cmp_int_float(&that.as_num().unwrap(), *a).reverse(),
+                (_, Self::Float(Float(b))) => cmp_int_float(&self.as_num().unwrap(), *b),
+                _ => self.as_num().cmp(&that.as_num()),
+            },
+            (3, 3) => self.as_ptr().cmp(&that.as_ptr()),
+            (0, 0) => self.poison_reason().cmp(&that.poison_reason()),
+            (1, 1) => self.as_char().cmp(&that.as_char()),
+            (r, s) => r.cmp(&s),
+        }
+    }
+}
+
+fn cmp_int_float(n: &BigInt, f: f64) -> Ordering {
+    if f.is_nan() {
+        if f.is_sign_negative() { Ordering::Greater } else { Ordering::Less }
+    } else if f.is_infinite() {
+        if f < 0.0 { Ordering::Greater } else { Ordering::Less }
+    } else {
+        match n.cmp(&BigInt::from_f64(f.floor()).unwrap()) {
+            Ordering::Equal if f.fract() != 0.0 => Ordering::Less,
+            ordering => ordering,
+        }
+    }
+}
+
 impl Val {
     pub fn as_i64(&self) -> Option<i64> {
         self.as_num()?.to_i64()
@@ -221,34 +299,54 @@ impl Val {
             Val::List(values) => {
                 match shape {
                     Shape::Array(elem_shape, _) | Shape::List(elem_shape) => {
-                        if **elem_shape == Shape::Char {
+                        let limit = text.options().list_limit;
+                        let skipped = if values.len() > limit {
+                            limit / 2 .. values.len() - limit / 2
+                        } else {
+                            0 .. 0
+                        };
+                        if **elem_shape == Shape::Char && !text.options().expand_strings {
                             let s = self.as_string().unwrap();
                             let s = if s.is_empty() {
                                 "ε".to_string()
-                            } else {
+                            } else if skipped.is_empty() {
                                 s.replace('\n', "↵")
+                            } else {
+                                let head: String = s.chars().take(skipped.start).collect();
+                                let tail: String = s.chars().skip(skipped.end).collect();
+                                format!("{}…{}…{}", head.replace('\n', "↵"), values.len(), tail.replace('\n', "↵"))
                             };
                             text.write_str(Color::Green, Color::Black, &s);
                         } else {
-                            text.write_str_default("[");
+                            let bracket = match shape {
+                                Shape::List(_) => Color::Cyan,
+                                _ => Color::White,
+                            };
+                            text.write_str(bracket, Color::Black, "[");
                             for (i, value) in values.iter().enumerate() {
+                                if skipped.contains(&i) {
+                                    if i == skipped.start {
+                                        text.write_str(Color::Grey, Color::Black, &format!(" …{}…", values.len()));
+                                    }
+                                    continue;
+                                }
                                 if i > 0 {
                                     text.write_str_default(" ");
                                 }
                                 value.shaped_text(elem_shape, text);
                             }
-                            text.write_str_default("]");
+                            text.write_str(bracket, Color::Black, "]");
                         }
                     },
                     Shape::Tuple(shapes) => {
-                        text.write_str_default("[");
+                        text.write_str(Color::Magenta, Color::Black, "[");
                         for (i, (value, elem_shape)) in values.iter().zip(shapes.iter()).enumerate() {
                             if i > 0 {
                                 text.write_str_default(" ");
                             }
                             value.shaped_text(elem_shape, text);
                         }
-                        text.write_str_default("]");
+                        text.write_str(Color::Magenta, Color::Black, "]");
                     },
                     _ => {
                         self.get_text(text);
@@ -285,8 +383,8 @@ impl Val {
 }
 
 impl Value {
-    pub fn new_poison() -> Self {
-        Self::Poison
+    pub fn new_poison_with(reason: &str) -> Self {
+        Self::Poison(Some(reason.to_string()))
     }
 
     pub fn new_char(c: char) -> Self {
@@ -297,6 +395,10 @@ impl Value {
         Self::Num(val)
     }
 
+    pub fn new_float(val: f64) -> Self {
+        Self::Float(Float(val))
+    }
+
     pub fn new_num(val: BigInt) -> Self {
         match val.to_i64() {
             Some(n) => Self::Num(n),
@@ -309,7 +411,7 @@ impl Value {
     }
 
     pub fn new_bool(val: bool) -> Self {
-        Self::new_i64(if val { 1 } else { 0 })
+        Self::Bool(val)
     }
 
     pub fn new_list(val: Vec<Value>) -> Self {
@@ -338,6 +440,7 @@ impl Value {
     pub fn as_i64(&self) -> Option<i64> {
         match self {
             Self::Num(n) => Some(*n),
+            Self::Bool(b) => Some(*b as i64),
             _ => self.as_ptr()?.as_i64(),
         }
     }
@@ -345,6 +448,7 @@ impl Value {
     pub fn as_usize(&self) -> Option<usize> {
         match self {
             Self::Num(n) => (*n).try_into().ok(),
+            Self::Bool(b) => Some(*b as usize),
             _ => self.as_ptr()?.as_usize(),
         }
     }
@@ -352,10 +456,37 @@ impl Value {
     pub fn as_num(&self) -> Option<BigInt> {
         match self {
             Self::Num(n) => Some((*n).into()),
+            Self::Bool(b) => Some((*b as i64).into()),
             _ => self.as_ptr()?.as_num(),
         }
     }
 
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Self::Float(Float(f)) => Some(*f),
+            _ => self.as_num()?.to_f64(),
+        }
+    }
+
+    pub fn is_num(&self) -> bool {
+        self.rank() == 2
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Self::Float(_))
+    }
+
+    pub fn is_poison(&self) -> bool {
+        matches!(self, Self::Poison(_))
+    }
+
+    pub fn poison_reason(&self) -> Option<&str> {
+        match self {
+            Self::Poison(reason) => reason.as_deref(),
+            _ => None,
+        }
+    }
+
     pub fn as_bool(&self) -> Option<bool> {
         match self.as_i64()? {
             0 => Some(false),
@@ -364,6 +495,18 @@ impl Value {
         }
     }
 
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Poison(_) => 0,
+            Self::Char(_) => 1,
+            Self::Bool(_) | Self::Num(_) | Self::Float(_) => 2,
+            Self::Ptr(val) => match **val {
+                Val::Num(_) => 2,
+                _ => 3,
+            },
+        }
+    }
+
     fn as_ptr(&self) -> Option<&Val> {
         match self {
             Self::Ptr(val) => Some(val),
@@ -391,19 +534,50 @@ impl Value {
         self.as_ptr()?.as_string()
     }
 
+    /// Structural equality independent of rendering shape: numbers by value, lists
+    /// elementwise, sets by multiplicity, quotes by program, and poison never equal.
+    pub fn deep_eq(&self, that: &Value) -> bool {
+        match (self, that) {
+            (Self::Poison(_), _) | (_, Self::Poison(_)) => false,
+            (Self::Char(a), Self::Char(b)) => a == b,
+            (Self::Float(_), _) | (_, Self::Float(_)) => self == that,
+            _ => match (self.as_ptr(), that.as_ptr()) {
+                (Some(Val::List(a)), Some(Val::List(b))) => {
+                    a.len() == b.len() && zip(a, b).all(|(x, y)| x.deep_eq(y))
+                },
+                (Some(Val::Set(a)), Some(Val::Set(b))) => {
+                    a.iter().count() == b.iter().count()
+                        && zip(a, b).all(|((x, m), (y, n))| m == n && x.deep_eq(y))
+                },
+                (Some(Val::Quote(a)), Some(Val::Quote(b))) => {
+                    a.local_program() == b.local_program()
+                },
+                _ => match (self.as_num(), that.as_num()) {
+                    (Some(a), Some(b)) => a == b,
+                    _ => false,
+                },
+            },
+        }
+    }
+
     pub fn shape(&self) -> Shape {
         match self {
-            Self::Poison => Shape::Any,
+            Self::Poison(_) => Shape::Any,
+            Self::Bool(_) => Shape::Bool,
             Self::Char(_) => Shape::Char,
             Self::Num(_) => Shape::Num,
+            Self::Float(_) => Shape::Float,
             Self::Ptr(v) => v.shape(),
         }
     }
 
     fn shaped_text(&self, shape: &Shape, text: &mut TextBuilder) {
         match self {
-            Self::Poison => {
+            Self::Poison(reason) => {
                 text.write_str(Color::Black, Color::White, "☠");
+                if let Some(reason) = reason {
+                    text.write_str(Color::Grey, Color::Black, &format!("({reason})"));
+                }
             },
             Self::Char(c) => {
                 let c = match *c {
@@ -413,9 +587,15 @@ impl Value {
                 };
                 text.write_char(Color::Green, Color::Black, c);
             },
+            Self::Bool(b) => {
+                text.write_str(Color::Green, Color::Black, &format!("{b}"));
+            },
             Self::Num(n) => {
                 text.write_str(Color::Green, Color::Black, &format!("{n}"));
             },
+            Self::Float(Float(f)) => {
+                text.write_str(Color::Green, Color::Black, &format!("{f:?}"));
+            },
             Self::Ptr(val) => {
                 val.shaped_text(shape, text);
             },
@@ -434,3 +614,43 @@ impl PrettyText for Value {
         self.shaped_text(&self.shape(), text);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pretty::TextOptions;
+
+    fn iota(n: i64) -> Value {
+        Value::new_list((0 .. n).map(Value::new_i64).collect())
+    }
+
+    #[test]
+    fn long_lists_are_truncated() {
+        let options = TextOptions { list_limit: 4, ..TextOptions::default() };
+        assert_eq!(iota(4).plain_text(options), "[0 1 2 3]");
+        assert_eq!(iota(100).plain_text(options), "[0 1 …100… 98 99]");
+    }
+
+    #[test]
+    fn strings_expand_on_request() {
+        let options = TextOptions { expand_strings: true, ..TextOptions::default() };
+        assert_eq!(Value::new_str("abc").plain_text(TextOptions::default()), "abc");
+        assert_eq!(Value::new_str("abc").plain_text(options), "[a b c]");
+    }
+
+    fn bracket_colors(value: &Value) -> Vec<Color> {
+        let mut text = TextBuilder::new();
+        value.get_text(&mut text);
+        text.symbols().into_iter().filter(|symbol| symbol.glyph == '[').map(|symbol| symbol.foreground).collect()
+    }
+
+    #[test]
+    fn shapes_render_distinctly() {
+        let array = iota(2);
+        let tuple = Value::new_list(vec![Value::new_i64(1), Value::new_str("a")]);
+        let lists = Value::new_list((1 ..= 9).map(iota).collect());
+        assert_eq!(bracket_colors(&array), [Color::White]);
+        assert_eq!(bracket_colors(&tuple), [Color::Magenta]);
+        assert_eq!(bracket_colors(&lists)[.. 2], [Color::White, Color::Cyan]);
+    }
+}